@@ -0,0 +1,396 @@
+use crate::serve::ErrorResponse;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::Instant;
+use tonic::metadata::MetadataMap;
+
+/// What an API key is allowed to do. Admin keys can also read.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// list, describe and search collections and embed texts
+    Read,
+    /// also modify collections
+    Admin,
+}
+
+/// An entry of the API keys file.
+#[derive(Deserialize)]
+struct ApiKeyEntry {
+    /// name shown in logs instead of the key
+    name: Option<String>,
+    /// the key itself
+    key: Option<String>,
+    /// hex-encoded sha256 digest of the key, to avoid storing it in plain text
+    key_sha256: Option<String>,
+    /// collections the key can access, or `"*"` for all of them
+    #[serde(default = "all_collections")]
+    collections: Vec<String>,
+    role: Role,
+}
+
+fn all_collections() -> Vec<String> {
+    vec![String::from("*")]
+}
+
+#[derive(Deserialize)]
+struct ApiKeysFile {
+    keys: Vec<ApiKeyEntry>,
+}
+
+/// An authenticated API key.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub name: String,
+    pub collections: Vec<String>,
+    pub role: Role,
+}
+
+impl ApiKey {
+    pub fn can_access(&self, collection: &str) -> bool {
+        self.collections.iter().any(|c| c == "*" || c == collection)
+    }
+
+    /// Whether the key has `role` on `collection`, or on the server itself if `collection` is `None`.
+    pub fn allows(&self, collection: Option<&str>, role: Role) -> bool {
+        self.role >= role && collection.is_none_or(|collection| self.can_access(collection))
+    }
+}
+
+/// API keys loaded from a JSON file such as
+/// `{"keys": [{"name": "app", "key": "...", "collections": ["docs"], "role": "read"}]}`.
+pub struct ApiKeys {
+    /// keys by the hex-encoded sha256 digest of the key
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file: ApiKeysFile = serde_json::from_reader(File::open(path).map_err(|e| {
+            anyhow::anyhow!("Cannot open API keys file {}: {}", path.display(), e)
+        })?)?;
+
+        let mut keys = HashMap::new();
+        for (i, entry) in file.keys.into_iter().enumerate() {
+            let digest = match (entry.key, entry.key_sha256) {
+                (Some(key), None) if !key.is_empty() => sha256_hex(&key),
+                (None, Some(digest))
+                    if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) =>
+                {
+                    digest.to_lowercase()
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "API key #{} in {} must have either a non-empty key or a 64-character hex key_sha256",
+                        i + 1,
+                        path.display()
+                    ))
+                }
+            };
+            let api_key = ApiKey {
+                name: entry.name.unwrap_or_else(|| format!("key #{}", i + 1)),
+                collections: entry.collections,
+                role: entry.role,
+            };
+            if keys.insert(digest, api_key).is_some() {
+                return Err(anyhow::anyhow!(
+                    "API key #{} in {} is given more than once",
+                    i + 1,
+                    path.display()
+                ));
+            }
+        }
+
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("No API keys in {}", path.display()));
+        }
+
+        Ok(ApiKeys { keys })
+    }
+
+    pub fn authenticate(&self, key: &str) -> Option<&ApiKey> {
+        self.keys.get(&sha256_hex(key))
+    }
+
+    /// Authenticates the key sent in the `Authorization: Bearer <key>` or `X-API-Key` header.
+    pub fn authenticate_headers(&self, headers: &HeaderMap) -> Option<&ApiKey> {
+        self.authenticate(key_from_headers(|name| headers.get(name)?.to_str().ok())?)
+    }
+
+    /// Same as `authenticate_headers` for the metadata of a gRPC request.
+    pub fn authenticate_metadata(&self, metadata: &MetadataMap) -> Option<&ApiKey> {
+        self.authenticate(key_from_headers(|name| metadata.get(name)?.to_str().ok())?)
+    }
+}
+
+fn sha256_hex(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Key sent as a bearer token, or else in the `X-API-Key` header, e.g. when `Authorization`
+/// is used by a proxy. `header` returns the value of a header by its lowercase name.
+fn key_from_headers<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Option<&'a str> {
+    header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"))
+}
+
+/// Access a request needs: the collection it's about, if any, and the role.
+/// `None` means the route is public.
+fn required_access(method: &Method, path: &str) -> Option<(Option<String>, Role)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        // health check and API docs
+        [""] | ["openapi.json"] | ["docs", ..] => None,
+        ["collections"] if method == Method::GET => Some((None, Role::Read)),
+        ["collections", name, rest @ ..] => {
+            let read = match rest {
+                [] | ["stats"] => method == Method::GET,
+                ["search"] => method == Method::POST,
+                _ => false,
+            };
+            let role = if read { Role::Read } else { Role::Admin };
+            Some((Some(name.to_string()), role))
+        }
+        ["v1", "embeddings"] => Some((None, Role::Read)),
+        _ => Some((None, Role::Admin)),
+    }
+}
+
+/// Middleware that checks the API key of requests when the server has `ApiKeys` in its app data.
+/// The authenticated `ApiKey` is added to the request extensions for the handlers.
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let api_keys = match req.app_data::<web::Data<ApiKeys>>() {
+        Some(api_keys) => api_keys.clone(),
+        None => return Ok(next.call(req).await?.map_into_left_body()),
+    };
+    let (collection, role) = match required_access(req.method(), req.path()) {
+        Some(access) => access,
+        None => return Ok(next.call(req).await?.map_into_left_body()),
+    };
+
+    let api_key = match api_keys.authenticate_headers(req.headers()) {
+        Some(api_key) => api_key.clone(),
+        None => {
+            let response = HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .json(ErrorResponse::new(
+                    String::from("Missing or invalid API key"),
+                    start,
+                ));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    if !api_key.allows(collection.as_deref(), role) {
+        let message = match collection {
+            Some(collection) => format!(
+                "API key '{}' is not allowed to {} collection '{}'",
+                api_key.name,
+                if role == Role::Read { "read" } else { "modify" },
+                collection
+            ),
+            None => format!(
+                "API key '{}' is not allowed to perform this operation",
+                api_key.name
+            ),
+        };
+        let response = HttpResponse::Forbidden().json(ErrorResponse::new(message, start));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    req.extensions_mut().insert(api_key);
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{require_api_key, required_access, sha256_hex, ApiKey, ApiKeys, Role};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use std::collections::HashMap;
+    use std::fs;
+    use tonic::metadata::MetadataMap;
+
+    #[test]
+    fn test_api_keys() {
+        let path = std::env::temp_dir().join("test_api_keys.json");
+        fs::write(
+            &path,
+            r#"{"keys": [
+                {"name": "docs-reader", "key": "reader-key", "collections": ["docs"], "role": "read"},
+                {"key_sha256": "2BB80D537B1DA3E38BD30361AA855686BDE0EACD7162FEF6A25FE97BF527A25B", "role": "admin"}
+            ]}"#,
+        )
+        .unwrap();
+        let api_keys = ApiKeys::from_file(&path).unwrap();
+
+        let reader = api_keys.authenticate("reader-key").unwrap();
+        assert_eq!(reader.name, "docs-reader");
+        assert!(reader.allows(Some("docs"), Role::Read));
+        assert!(!reader.allows(Some("docs"), Role::Admin));
+        assert!(!reader.allows(Some("other"), Role::Read));
+        assert!(reader.allows(None, Role::Read));
+
+        // sha256 of "secret"
+        let admin = api_keys.authenticate("secret").unwrap();
+        assert!(admin.allows(Some("other"), Role::Admin));
+        assert!(api_keys.authenticate("unknown").is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-api-key"),
+            HeaderValue::from_static("reader-key"),
+        );
+        assert!(api_keys.authenticate_headers(&headers).is_some());
+        headers.insert(
+            HeaderName::from_static("authorization"),
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert_eq!(
+            api_keys.authenticate_headers(&headers).unwrap().role,
+            Role::Admin
+        );
+
+        // the key is also read from X-API-Key when Authorization is not a bearer token
+        headers.insert(
+            HeaderName::from_static("authorization"),
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+        assert_eq!(
+            api_keys.authenticate_headers(&headers).unwrap().name,
+            "docs-reader"
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", "Bearer secret".parse().unwrap());
+        assert!(api_keys.authenticate_metadata(&metadata).is_some());
+
+        fs::write(&path, r#"{"keys": [{"role": "read"}]}"#).unwrap();
+        assert!(ApiKeys::from_file(&path).is_err());
+        let not_hex = "z".repeat(64);
+        fs::write(
+            &path,
+            format!(
+                r#"{{"keys": [{{"key_sha256": "{}", "role": "read"}}]}}"#,
+                not_hex
+            ),
+        )
+        .unwrap();
+        assert!(ApiKeys::from_file(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_required_access() {
+        assert_eq!(required_access(&Method::GET, "/"), None);
+        assert_eq!(required_access(&Method::GET, "/docs/index.html"), None);
+        assert_eq!(
+            required_access(&Method::GET, "/collections"),
+            Some((None, Role::Read))
+        );
+        assert_eq!(
+            required_access(&Method::POST, "/collections/docs/search"),
+            Some((Some(String::from("docs")), Role::Read))
+        );
+        assert_eq!(
+            required_access(&Method::GET, "/collections/docs/stats"),
+            Some((Some(String::from("docs")), Role::Read))
+        );
+        assert_eq!(
+            required_access(&Method::POST, "/collections/docs/reload"),
+            Some((Some(String::from("docs")), Role::Admin))
+        );
+        assert_eq!(
+            required_access(&Method::POST, "/v1/embeddings"),
+            Some((None, Role::Read))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_require_api_key() {
+        let api_keys = ApiKeys {
+            keys: HashMap::from([(
+                sha256_hex("reader-key"),
+                ApiKey {
+                    name: String::from("docs-reader"),
+                    collections: vec![String::from("docs")],
+                    role: Role::Read,
+                },
+            )]),
+        };
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(api_keys))
+                .wrap(from_fn(require_api_key))
+                .route("/", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/collections/{collection_name}",
+                    web::get().to(HttpResponse::Ok),
+                )
+                .route(
+                    "/collections/{collection_name}/reload",
+                    web::post().to(HttpResponse::Ok),
+                ),
+        )
+        .await;
+
+        let cases = [
+            (Method::GET, "/", None, StatusCode::OK),
+            (
+                Method::GET,
+                "/collections/docs",
+                None,
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                Method::GET,
+                "/collections/docs",
+                Some("wrong-key"),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                Method::GET,
+                "/collections/docs",
+                Some("reader-key"),
+                StatusCode::OK,
+            ),
+            (
+                Method::GET,
+                "/collections/other",
+                Some("reader-key"),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                Method::POST,
+                "/collections/docs/reload",
+                Some("reader-key"),
+                StatusCode::FORBIDDEN,
+            ),
+        ];
+        for (method, uri, key, status) in cases {
+            let mut req = TestRequest::default().method(method).uri(uri);
+            if let Some(key) = key {
+                req = req.insert_header(("Authorization", format!("Bearer {}", key)));
+            }
+            let res = call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), status, "{}", uri);
+        }
+    }
+}
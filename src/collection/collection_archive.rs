@@ -0,0 +1,888 @@
+use super::collection_utils::{
+    collection_dir, home_dir, server_lock_holder, validate_collection_name, CollectionConfig,
+};
+use super::migrations::CURRENT_SERIALIZATION_VERSION;
+use super::vector_index::read_index_header;
+use crate::hf_ops::{
+    commit_dataset_files, download_dataset_file, hf_endpoint, is_offline, offline_error,
+    parse_dataset_repo, resolve_cached_model, sha256_file, BlobLfsInfo, RepoFile, UploadFile,
+};
+use crate::model::model_manager::ModelManager;
+use crate::model::model_utils::{resolve_local_model, Backend};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+pub const ARCHIVE_MANIFEST_FILE: &str = "manifest.json";
+pub const SUPPORTED_ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ArchiveFile {
+    /// path relative to the collection directory
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ArchiveIndex {
+    pub column: String,
+    pub dimensions: u64,
+    pub count: u64,
+}
+
+/// Describes the contents of a collection archive. It's stored as `manifest.json` at the root of the archive.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ArchiveManifest {
+    pub archive_version: u32,
+    pub letsearch_version: String,
+    pub collection_name: String,
+    pub serialization_version: u32,
+    pub model_name: String,
+    pub model_revision: Option<String>,
+    pub model_variant: String,
+    pub indexes: Vec<ArchiveIndex>,
+    pub files: Vec<ArchiveFile>,
+}
+
+impl ArchiveManifest {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let manifest: ArchiveManifest = serde_json::from_reader(File::open(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid archive manifest: {}", e))?;
+        if manifest.archive_version > SUPPORTED_ARCHIVE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Archive version {} is not supported by this version of letsearch. Supported version is {}",
+                manifest.archive_version,
+                SUPPORTED_ARCHIVE_VERSION
+            ));
+        }
+
+        // paths are joined to the directory the files are extracted or downloaded into
+        for file in manifest.files.iter() {
+            if !is_relative_path(file.path.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Invalid archive manifest: {} is not a relative path inside the collection",
+                    file.path
+                ));
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Whether `path` stays inside the directory it's joined to.
+fn is_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Checks that the paths in the `config.json` of an archive point inside the collection directory.
+fn check_config_paths(manifest: &ArchiveManifest, config: &CollectionConfig) -> anyhow::Result<()> {
+    let columns = config
+        .index_columns
+        .iter()
+        .chain(manifest.indexes.iter().map(|index| &index.column));
+    for (field, path) in [
+        ("db_path", &config.db_path),
+        ("index_dir", &config.index_dir),
+    ] {
+        if !is_relative_path(path.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Invalid {} in config.json: {} is not a relative path inside the collection",
+                field,
+                path
+            ));
+        }
+    }
+    for column in columns {
+        if !is_relative_path(column.as_str())
+            || Path::new(column.as_str()).components().count() != 1
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid index column in config.json: {}",
+                column
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn archive_file(dir: &Path, path: &str) -> anyhow::Result<ArchiveFile> {
+    let full_path = dir.join(path);
+    Ok(ArchiveFile {
+        path: path.to_string(),
+        size: fs::metadata(&full_path)?.len(),
+        sha256: sha256_file(&full_path)?,
+    })
+}
+
+/// Builds the manifest of the collection `name` listing `config.json`, the DuckDB file
+/// and the vector indexes with their checksums.
+pub fn build_manifest(name: &str) -> anyhow::Result<ArchiveManifest> {
+    let dir = collection_dir(name);
+    let config_path = dir.join("config.json");
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!("Collection '{}' does not exist", name));
+    }
+    let config: CollectionConfig = serde_json::from_reader(File::open(&config_path)?)?;
+
+    let mut files = vec![archive_file(&dir, "config.json")?];
+    if dir.join(config.db_path.as_str()).exists() {
+        files.push(archive_file(&dir, config.db_path.as_str())?);
+    }
+
+    let mut indexes = Vec::new();
+    for column in config.index_columns.iter() {
+        let index_path = format!("{}/{}/index.bin", config.index_dir, column);
+        if !dir.join(&index_path).exists() {
+            continue;
+        }
+
+        let header = read_index_header(&dir.join(&index_path))?;
+        indexes.push(ArchiveIndex {
+            column: column.clone(),
+            dimensions: header.dimensions,
+            count: header.count,
+        });
+        files.push(archive_file(&dir, index_path.as_str())?);
+    }
+
+    let manifest = ArchiveManifest {
+        archive_version: SUPPORTED_ARCHIVE_VERSION,
+        letsearch_version: env!("CARGO_PKG_VERSION").to_string(),
+        collection_name: config.name.clone(),
+        serialization_version: config.serialization_version,
+        model_name: config.model_name.clone(),
+        model_revision: config.model_revision.clone(),
+        model_variant: config.model_variant.clone(),
+        indexes,
+        files,
+    };
+
+    Ok(manifest)
+}
+
+/// Bundles `config.json`, the DuckDB file and the vector indexes of the collection `name`
+/// into a gzipped tar archive at `output` along with a checksummed manifest.
+pub fn pack_collection(name: &str, output: &Path) -> anyhow::Result<ArchiveManifest> {
+    let dir = collection_dir(name);
+    let manifest = build_manifest(name)?;
+    let encoder = GzEncoder::new(File::create(output)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(
+        &mut header,
+        ARCHIVE_MANIFEST_FILE,
+        manifest_bytes.as_slice(),
+    )?;
+    for file in manifest.files.iter() {
+        builder.append_path_with_name(dir.join(file.path.as_str()), file.path.as_str())?;
+    }
+    builder.into_inner()?.finish()?;
+
+    info!(
+        "Collection '{}' packed into {} with {} file(s)",
+        name,
+        output.display(),
+        manifest.files.len()
+    );
+
+    Ok(manifest)
+}
+
+/// Paths of the files under `dir`. Symbolic links are refused, as they could point outside of it.
+fn list_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            return Err(anyhow::anyhow!(
+                "{} is a symbolic link, which is not allowed in a collection archive",
+                path.display()
+            ));
+        } else if metadata.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Checks that every file in the manifest was extracted with the expected size and sha256
+/// and that nothing else was in the archive.
+fn verify_unpacked_files(dir: &Path, manifest: &ArchiveManifest) -> anyhow::Result<()> {
+    let listed: HashSet<PathBuf> = manifest
+        .files
+        .iter()
+        .map(|file| dir.join(file.path.as_str()))
+        .collect();
+    for path in list_files(dir)? {
+        if !listed.contains(&path) {
+            return Err(anyhow::anyhow!(
+                "{} is in the archive but not listed in the manifest",
+                path.strip_prefix(dir).unwrap_or(&path).display()
+            ));
+        }
+    }
+
+    for file in manifest.files.iter() {
+        let path = dir.join(file.path.as_str());
+        if !path.exists() {
+            return Err(anyhow::anyhow!(
+                "{} is listed in the manifest but missing in the archive",
+                file.path
+            ));
+        }
+
+        let size = fs::metadata(&path)?.len();
+        if size != file.size {
+            return Err(anyhow::anyhow!(
+                "Size mismatch for {}: expected {} bytes, got {}",
+                file.path,
+                file.size,
+                size
+            ));
+        }
+
+        let sha256 = sha256_file(&path)?;
+        if sha256 != file.sha256 {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch for {}: expected sha256 {}, got {}",
+                file.path,
+                file.sha256,
+                sha256
+            ));
+        }
+    }
+
+    let config: CollectionConfig = serde_json::from_reader(File::open(dir.join("config.json"))?)?;
+    if config.name != manifest.collection_name {
+        return Err(anyhow::anyhow!(
+            "Collection name in config.json ({}) does not match the manifest ({})",
+            config.name,
+            manifest.collection_name
+        ));
+    }
+
+    if config.serialization_version > CURRENT_SERIALIZATION_VERSION {
+        return Err(anyhow::anyhow!(
+            "Collection serialization version {} is newer than the supported version {}. Upgrade letsearch to unpack it",
+            config.serialization_version,
+            CURRENT_SERIALIZATION_VERSION
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks the indexes in the archive against the output dimension of the model if it's installed.
+async fn check_model_compatibility(manifest: &ArchiveManifest) -> anyhow::Result<()> {
+    let pinned_model_name = CollectionConfig {
+        model_name: manifest.model_name.clone(),
+        model_revision: manifest.model_revision.clone(),
+        ..CollectionConfig::default()
+    }
+    .pinned_model_name();
+    let resolved = if pinned_model_name.starts_with("hf://") {
+        resolve_cached_model(pinned_model_name.as_str(), manifest.model_variant.as_str())
+    } else {
+        resolve_local_model(pinned_model_name.as_str(), manifest.model_variant.as_str())
+    };
+    let (model_dir, _) = match resolved {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            warn!(
+                "Skipping the compatibility check with model {} ({}): {}",
+                pinned_model_name, manifest.model_variant, e
+            );
+            return Ok(());
+        }
+    };
+
+    let model_manager = ModelManager::new();
+    let model_id = model_manager
+        .load_model(
+            model_dir,
+            manifest.model_variant.clone(),
+            Backend::ONNX,
+            None,
+        )
+        .await?;
+    let output_dim = model_manager.output_dim(model_id).await? as u64;
+    for index in manifest.indexes.iter() {
+        if index.dimensions != output_dim {
+            return Err(anyhow::anyhow!(
+                "Index of column '{}' has {} dimensions but model {} ({}) outputs {}",
+                index.column,
+                index.dimensions,
+                pinned_model_name,
+                manifest.model_variant,
+                output_dim
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores a collection archive created by `pack_collection` into `LETSEARCH_HOME/collections`.
+///
+/// Files are verified against the manifest before the collection is moved into place,
+/// and the indexes are checked against the model if it's already installed.
+pub async fn unpack_collection(archive: &Path, overwrite: bool) -> anyhow::Result<ArchiveManifest> {
+    let staging_dir: PathBuf = home_dir().join(format!(".unpack-{}", std::process::id()));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+
+    let result = unpack_into(archive, &staging_dir, overwrite).await;
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+
+    result
+}
+
+async fn unpack_into(
+    archive: &Path,
+    staging_dir: &Path,
+    overwrite: bool,
+) -> anyhow::Result<ArchiveManifest> {
+    let mut tar_archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    for entry in tar_archive.entries()? {
+        // unpack_in refuses paths that would escape the staging directory
+        entry?.unpack_in(staging_dir)?;
+    }
+
+    let manifest_path = staging_dir.join(ARCHIVE_MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Err(anyhow::anyhow!(
+            "{} is not a letsearch collection archive: {} is missing",
+            archive.display(),
+            ARCHIVE_MANIFEST_FILE
+        ));
+    }
+    let manifest = ArchiveManifest::from_file(&manifest_path)?;
+    fs::remove_file(manifest_path)?;
+    install_collection(staging_dir, &manifest, overwrite).await?;
+    info!(
+        "Collection '{}' unpacked from {}",
+        manifest.collection_name,
+        archive.display()
+    );
+
+    Ok(manifest)
+}
+
+/// Moves the collection files in `staging_dir` into `LETSEARCH_HOME/collections`
+/// after verifying them against `manifest` and checking the compatibility with the model.
+pub async fn install_collection(
+    staging_dir: &Path,
+    manifest: &ArchiveManifest,
+    overwrite: bool,
+) -> anyhow::Result<()> {
+    validate_collection_name(manifest.collection_name.as_str())?;
+    verify_unpacked_files(staging_dir, manifest)?;
+    let config: CollectionConfig =
+        serde_json::from_reader(File::open(staging_dir.join("config.json"))?)?;
+    check_config_paths(manifest, &config)?;
+
+    let target_dir = collection_dir(manifest.collection_name.as_str());
+    if target_dir.exists() && !overwrite {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' already exists. Use overwrite to replace it",
+            manifest.collection_name
+        ));
+    }
+    if let Some(pid) = server_lock_holder(manifest.collection_name.as_str()) {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' is currently being served by process {}. Stop the server before replacing it",
+            manifest.collection_name,
+            pid
+        ));
+    }
+    check_model_compatibility(manifest).await?;
+
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir)?;
+    }
+    fs::create_dir_all(target_dir.parent().unwrap())?;
+    fs::rename(staging_dir, &target_dir)?;
+
+    Ok(())
+}
+
+/// Uploads the collection `name` to the dataset at `dataset_path` (`hf://datasets/user/repo[@revision]`)
+/// in a single commit along with its manifest. The dataset is created if it does not exist.
+/// Returns the URL of the commit.
+pub async fn push_collection(
+    name: &str,
+    dataset_path: &str,
+    private: bool,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    if is_offline() {
+        return Err(offline_error("push collections"));
+    }
+
+    push_collection_to(hf_endpoint().as_str(), name, dataset_path, private, token).await
+}
+
+async fn push_collection_to(
+    endpoint: &str,
+    name: &str,
+    dataset_path: &str,
+    private: bool,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    let token = token.ok_or_else(|| {
+        anyhow::anyhow!("A HuggingFace token with write access is required to push collections")
+    })?;
+    let dir = collection_dir(name);
+    let manifest = build_manifest(name)?;
+
+    // JSON files are committed inline, the DuckDB file and the vector indexes go to LFS storage
+    let mut inline_files = vec![(
+        ARCHIVE_MANIFEST_FILE.to_string(),
+        serde_json::to_vec_pretty(&manifest)?,
+    )];
+    let mut lfs_files = Vec::new();
+    for file in manifest.files.iter() {
+        if file.path.ends_with(".json") {
+            inline_files.push((file.path.clone(), fs::read(dir.join(file.path.as_str()))?));
+        } else {
+            lfs_files.push(UploadFile {
+                path: file.path.clone(),
+                local_path: dir.join(file.path.as_str()),
+                size: file.size,
+                sha256: file.sha256.clone(),
+            });
+        }
+    }
+
+    let commit_url = commit_dataset_files(
+        endpoint,
+        dataset_path,
+        &inline_files,
+        &lfs_files,
+        format!("Upload collection {}", name).as_str(),
+        private,
+        token.as_str(),
+    )
+    .await?;
+    info!("Collection '{}' pushed to {}", name, commit_url);
+
+    Ok(commit_url)
+}
+
+/// Downloads a collection pushed with `push_collection` from `dataset_path` (`hf://datasets/user/repo[@revision]`)
+/// into `LETSEARCH_HOME/collections`. Interrupted downloads are resumed on the next pull.
+pub async fn pull_collection(
+    dataset_path: &str,
+    overwrite: bool,
+    token: Option<String>,
+) -> anyhow::Result<ArchiveManifest> {
+    pull_collection_from(hf_endpoint().as_str(), dataset_path, overwrite, token).await
+}
+
+async fn pull_collection_from(
+    endpoint: &str,
+    dataset_path: &str,
+    overwrite: bool,
+    token: Option<String>,
+) -> anyhow::Result<ArchiveManifest> {
+    let (repo_id, revision) = parse_dataset_repo(dataset_path)?;
+    let revision = revision.unwrap_or_else(|| String::from("main"));
+    let pull_dir = home_dir().join(".pull");
+    let staging_dir = pull_dir.join(repo_id.as_str());
+    // the staging directory is removed once the collection is installed
+    fs::create_dir_all(&staging_dir)?;
+    if !staging_dir
+        .canonicalize()?
+        .starts_with(pull_dir.canonicalize()?)
+    {
+        return Err(anyhow::anyhow!(
+            "{} can't be pulled into {}",
+            dataset_path,
+            pull_dir.display()
+        ));
+    }
+
+    // the manifest might have changed since the last pull
+    let manifest_path = staging_dir.join(ARCHIVE_MANIFEST_FILE);
+    if manifest_path.exists() {
+        fs::remove_file(&manifest_path)?;
+    }
+    download_dataset_file(
+        endpoint,
+        repo_id.as_str(),
+        revision.as_str(),
+        ARCHIVE_MANIFEST_FILE,
+        staging_dir.clone(),
+        token.clone(),
+        None,
+    )
+    .await?;
+    let manifest = ArchiveManifest::from_file(&manifest_path)?;
+    fs::remove_file(&manifest_path)?;
+
+    for file in manifest.files.iter() {
+        let expected = RepoFile {
+            rfilename: file.path.clone(),
+            size: Some(file.size),
+            blobId: None,
+            lfs: Some(BlobLfsInfo {
+                size: Some(file.size),
+                sha256: Some(file.sha256.clone()),
+                pointer_size: None,
+            }),
+        };
+        // files left over from a pull of another revision are downloaded again
+        download_dataset_file(
+            endpoint,
+            repo_id.as_str(),
+            revision.as_str(),
+            file.path.as_str(),
+            staging_dir.clone(),
+            token.clone(),
+            Some(&expected),
+        )
+        .await?;
+    }
+
+    // and files that are no longer in the collection are removed
+    let listed: HashSet<PathBuf> = manifest
+        .files
+        .iter()
+        .map(|file| staging_dir.join(file.path.as_str()))
+        .collect();
+    for path in list_files(&staging_dir)? {
+        if !listed.contains(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let result = install_collection(&staging_dir, &manifest, overwrite).await;
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    result?;
+    info!(
+        "Collection '{}' pulled from {}",
+        manifest.collection_name, dataset_path
+    );
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_config_paths, pack_collection, pull_collection_from, push_collection_to,
+        unpack_collection, ArchiveIndex, ArchiveManifest,
+    };
+    use crate::collection::collection_type::Collection;
+    use crate::collection::collection_utils::{collection_dir, CollectionConfig, ServerLock};
+    use crate::hf_ops::tests::start_writable_hub;
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    /// Copies `archive` to `output`, replacing `from` with `to` in the file at `path`
+    /// and appending the `extra` files.
+    fn repack(
+        archive: &Path,
+        output: &Path,
+        (path, from, to): (&str, &str, &str),
+        extra: &[(&str, &str)],
+    ) {
+        let mut source = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()));
+        let encoder = GzEncoder::new(File::create(output).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for entry in source.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let entry_path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut header = entry.header().clone();
+            if entry_path == path {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content).unwrap();
+                let content = content.replace(from, to);
+                header.set_size(content.len() as u64);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, entry_path, content.as_bytes())
+                    .unwrap();
+            } else {
+                builder
+                    .append_data(&mut header, entry_path, &mut entry)
+                    .unwrap();
+            }
+        }
+        for (path, content) in extra {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pack_unpack() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_archive");
+        config.model_name = String::from("hf://letsearch-test/not-installed");
+        let tmp_dir = std::env::temp_dir();
+        let jsonl_path = tmp_dir.join("test_archive.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"text\": \"first\"}\n{\"text\": \"second\"}\n",
+        )
+        .unwrap();
+        {
+            let collection = Collection::new(config, true).await.unwrap();
+            collection
+                .import_jsonl(jsonl_path.to_str().unwrap())
+                .await
+                .unwrap();
+        }
+
+        let archive = tmp_dir.join("test_archive.tar.gz");
+        let manifest = pack_collection("test_archive", &archive).unwrap();
+        assert_eq!(manifest.collection_name, "test_archive");
+        assert_eq!(
+            manifest
+                .files
+                .iter()
+                .map(|f| f.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["config.json", "data.db"]
+        );
+
+        assert!(unpack_collection(&archive, false).await.is_err());
+        fs::remove_dir_all(collection_dir("test_archive")).unwrap();
+        unpack_collection(&archive, false).await.unwrap();
+        let collection = Collection::from("test_archive".to_string()).await.unwrap();
+        assert_eq!(collection.stats().await.unwrap().row_count, 2);
+        drop(collection);
+
+        // repack with a tampered config.json but the original manifest
+        let tampered = tmp_dir.join("test_archive_tampered.tar.gz");
+        repack(
+            &archive,
+            &tampered,
+            ("config.json", "not-installed", "tampered"),
+            &[],
+        );
+        let error = unpack_collection(&tampered, true).await.unwrap_err();
+        assert!(error.to_string().contains("config.json"));
+        assert!(collection_dir("test_archive").join("data.db").exists());
+
+        // files that are not in the manifest are refused
+        repack(
+            &archive,
+            &tampered,
+            ("config.json", "", ""),
+            &[("index/extra.bin", "extra")],
+        );
+        let error = unpack_collection(&tampered, true).await.unwrap_err();
+        assert!(error.to_string().contains("extra.bin"));
+
+        // and so are collection names that are not valid
+        repack(
+            &archive,
+            &tampered,
+            ("manifest.json", "\"test_archive\"", "\"../test_archive\""),
+            &[],
+        );
+        let error = unpack_collection(&tampered, true).await.unwrap_err();
+        assert!(error.to_string().contains("../test_archive"));
+        assert!(collection_dir("test_archive").join("data.db").exists());
+
+        // a collection that is being served is not replaced
+        let lock = ServerLock::acquire("test_archive").unwrap();
+        let error = unpack_collection(&archive, true).await.unwrap_err();
+        assert!(error.to_string().contains("being served"));
+        drop(lock);
+        unpack_collection(&archive, true).await.unwrap();
+
+        fs::remove_dir_all(collection_dir("test_archive")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+        fs::remove_file(archive).unwrap();
+        fs::remove_file(tampered).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_paths() {
+        let path = std::env::temp_dir().join("test_manifest_paths.json");
+        let manifest = |file_path: &str| {
+            serde_json::json!({
+                "archive_version": 1,
+                "letsearch_version": "0.1.0",
+                "collection_name": "test",
+                "serialization_version": 1,
+                "model_name": "hf://letsearch-test/model",
+                "model_revision": null,
+                "model_variant": "f32",
+                "indexes": [],
+                "files": [{"path": file_path, "size": 1, "sha256": "0"}],
+            })
+            .to_string()
+        };
+
+        fs::write(&path, manifest("index/text/index.bin")).unwrap();
+        assert!(ArchiveManifest::from_file(&path).is_ok());
+        for file_path in [
+            "../config.json",
+            "/etc/passwd",
+            "index/../../data.db",
+            "./data.db",
+            "",
+        ] {
+            fs::write(&path, manifest(file_path)).unwrap();
+            assert!(ArchiveManifest::from_file(&path).is_err(), "{}", file_path);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_config_paths() {
+        let manifest = ArchiveManifest {
+            archive_version: 1,
+            letsearch_version: String::from("0.1.0"),
+            collection_name: String::from("test"),
+            serialization_version: 1,
+            model_name: String::from("hf://letsearch-test/model"),
+            model_revision: None,
+            model_variant: String::from("f32"),
+            indexes: vec![],
+            files: vec![],
+        };
+        let config = CollectionConfig {
+            index_columns: vec![String::from("text")],
+            ..CollectionConfig::default()
+        };
+        assert!(check_config_paths(&manifest, &config).is_ok());
+
+        for db_path in ["/tmp/data.db", "../data.db", ""] {
+            let config = CollectionConfig {
+                db_path: db_path.to_string(),
+                ..config.clone()
+            };
+            assert!(
+                check_config_paths(&manifest, &config).is_err(),
+                "{}",
+                db_path
+            );
+        }
+        for index_dir in ["/tmp", "index/../.."] {
+            let config = CollectionConfig {
+                index_dir: index_dir.to_string(),
+                ..config.clone()
+            };
+            assert!(
+                check_config_paths(&manifest, &config).is_err(),
+                "{}",
+                index_dir
+            );
+        }
+        for column in ["..", "text/../../x", "/text"] {
+            let config = CollectionConfig {
+                index_columns: vec![column.to_string()],
+                ..config.clone()
+            };
+            assert!(
+                check_config_paths(&manifest, &config).is_err(),
+                "{}",
+                column
+            );
+
+            let mut manifest = manifest.clone();
+            manifest.indexes = vec![ArchiveIndex {
+                column: column.to_string(),
+                dimensions: 384,
+                count: 1,
+            }];
+            assert!(check_config_paths(&manifest, &CollectionConfig::default()).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_pull_collection() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_push_pull");
+        config.model_name = String::from("hf://letsearch-test/not-installed");
+        let jsonl_path = std::env::temp_dir().join("test_push_pull.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"text\": \"first\"}\n{\"text\": \"second\"}\n",
+        )
+        .unwrap();
+        {
+            let collection = Collection::new(config, true).await.unwrap();
+            collection
+                .import_jsonl(jsonl_path.to_str().unwrap())
+                .await
+                .unwrap();
+        }
+
+        let endpoint = start_writable_hub().await;
+        let dataset_path = "hf://datasets/letsearch-test/push-pull";
+        assert!(
+            push_collection_to(&endpoint, "test_push_pull", dataset_path, false, None)
+                .await
+                .is_err()
+        );
+        let commit_url = push_collection_to(
+            &endpoint,
+            "test_push_pull",
+            dataset_path,
+            false,
+            Some(String::from("token")),
+        )
+        .await
+        .unwrap();
+        assert!(commit_url.ends_with("/commit/abc123"));
+
+        // refuses to replace the existing collection without overwrite
+        assert!(pull_collection_from(&endpoint, dataset_path, false, None)
+            .await
+            .is_err());
+        fs::remove_dir_all(collection_dir("test_push_pull")).unwrap();
+        let manifest = pull_collection_from(&endpoint, dataset_path, false, None)
+            .await
+            .unwrap();
+        assert_eq!(manifest.collection_name, "test_push_pull");
+
+        let collection = Collection::from("test_push_pull".to_string())
+            .await
+            .unwrap();
+        assert_eq!(collection.stats().await.unwrap().row_count, 2);
+        drop(collection);
+
+        fs::remove_dir_all(collection_dir("test_push_pull")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+}
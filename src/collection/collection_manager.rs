@@ -1,16 +1,49 @@
 use crate::collection::collection_type::Collection;
+use crate::hf_ops::{is_offline, resolve_model_revision};
 use crate::model::model_manager::ModelManager;
-use crate::model::model_utils::Backend;
-use std::collections::HashMap;
+use crate::model::model_utils::{Backend, Embeddings, TextEmbeddings};
+use log::{info, warn};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use super::collection_utils::{CollectionConfig, SearchResult};
+use super::collection_utils::{CollectionConfig, CollectionStats, SearchOptions, SearchResult};
+
+/// Number of texts embedded at once by `CollectionManager::embed`.
+const EMBED_BATCH_SIZE: usize = 32;
+
+/// Error of `CollectionManager::search`, to tell invalid requests from failed searches.
+#[derive(Debug)]
+pub enum SearchError {
+    /// the collection is not loaded
+    CollectionNotFound(String),
+    /// the options don't apply to the collection, e.g. a column without an index
+    InvalidOptions(anyhow::Error),
+    /// e.g. the model failed to embed the query
+    Failed(anyhow::Error),
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::CollectionNotFound(name) => {
+                write!(f, "Collection '{}' does not exist", name)
+            }
+            SearchError::InvalidOptions(e) | SearchError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
 
 pub struct CollectionManager {
     collections: RwLock<HashMap<String, Arc<RwLock<Collection>>>>,
     model_manager: Arc<RwLock<ModelManager>>,
     model_lookup: RwLock<HashMap<(String, String), u32>>,
+    /// held for reading by requests between looking up a model and running it,
+    /// and for writing to unload models once no request uses them
+    models_in_use: RwLock<()>,
     token: Option<String>,
 }
 
@@ -20,75 +53,196 @@ impl CollectionManager {
             collections: RwLock::new(HashMap::new()),
             model_manager: Arc::new(RwLock::new(ModelManager::new())),
             model_lookup: RwLock::new(HashMap::new()),
+            models_in_use: RwLock::new(()),
             token: token,
         }
     }
 
     pub async fn load_collection(&self, name: String) -> anyhow::Result<()> {
-        let collection = Arc::new(RwLock::new(Collection::from(name.clone()).await.unwrap()));
-        let collection_guard = collection.read().await;
-        let requested_models = collection_guard.requested_models().await;
-        if !requested_models.is_empty() {
-            let manager_guard = self.model_manager.write().await;
-            for requested_model in requested_models {
-                let mut lookup_guard = self.model_lookup.write().await;
-                if !lookup_guard.contains_key(&requested_model) {
-                    let (model_path, model_variant) = requested_model.clone();
-                    let model_id = manager_guard
-                        .load_model(
-                            model_path.clone(),
-                            model_variant.clone(),
-                            Backend::ONNX,
-                            self.token.clone(),
-                        )
-                        .await
-                        .unwrap();
-                    lookup_guard.insert(requested_model, model_id);
+        let collection = self.open_collection(name.clone()).await?;
+        let mut collections = self.collections.write().await;
+        collections.insert(name, collection);
+
+        Ok(())
+    }
+
+    /// Opens the collection `name` and loads the models it needs, without serving it yet.
+    async fn open_collection(&self, name: String) -> anyhow::Result<Arc<RwLock<Collection>>> {
+        let collection = Arc::new(RwLock::new(Collection::from(name).await?));
+        let requested_models = collection.read().await.requested_models().await;
+        self.load_models(requested_models).await?;
+
+        Ok(collection)
+    }
+
+    /// Loads the models in `requested_models` that are not loaded yet.
+    /// They may be downloaded first, so no lock that searches need is held meanwhile.
+    async fn load_models(&self, requested_models: Vec<(String, String)>) -> anyhow::Result<()> {
+        for requested_model in requested_models {
+            if self
+                .model_lookup
+                .read()
+                .await
+                .contains_key(&requested_model)
+            {
+                continue;
+            }
+
+            let (model_path, model_variant) = requested_model.clone();
+            let manager_guard = self.model_manager.read().await;
+            let model_id = manager_guard
+                .load_model(model_path, model_variant, Backend::ONNX, self.token.clone())
+                .await?;
+            match self.model_lookup.write().await.entry(requested_model) {
+                // loaded by another collection in the meantime
+                Entry::Occupied(_) => manager_guard.unload_model(model_id).await,
+                Entry::Vacant(entry) => {
+                    entry.insert(model_id);
                 }
             }
         }
 
-        let mut collections = self.collections.write().await;
-        collections.insert(name.clone(), collection.clone());
-
         Ok(())
     }
 
+    /// Unloads the models that no loaded collection requests anymore,
+    /// e.g. after a reloaded collection was pinned to another revision.
+    /// They are unloaded once the requests that may still use them are done.
+    async fn unload_unused_models(&self) {
+        let _drained = self.models_in_use.write().await;
+        let mut requested_models = HashSet::new();
+        for collection in self.collections.read().await.values() {
+            requested_models.extend(collection.read().await.requested_models().await);
+        }
+
+        let manager_guard = self.model_manager.read().await;
+        let mut lookup_guard = self.model_lookup.write().await;
+        let unused_models: Vec<(String, String)> = lookup_guard
+            .keys()
+            .filter(|model| !requested_models.contains(*model))
+            .cloned()
+            .collect();
+        for model in unused_models {
+            if let Some(model_id) = lookup_guard.remove(&model) {
+                manager_guard.unload_model(model_id).await;
+                info!("Model {}:{} unloaded", model.0, model.1);
+            }
+        }
+    }
+
     pub async fn create_collection(
         &self,
-        config: CollectionConfig,
+        mut config: CollectionConfig,
         overwrite: bool,
     ) -> anyhow::Result<()> {
-        let name = config.name.clone();
-        let collection = Arc::new(RwLock::new(Collection::new(config, overwrite).await?));
-        let collection_guard = collection.read().await;
-        let requested_models = collection_guard.requested_models().await;
-        if !requested_models.is_empty() {
-            let manager_guard = self.model_manager.write().await;
-            for requested_model in requested_models {
-                let mut lookup_guard = self.model_lookup.write().await;
-                if !lookup_guard.contains_key(&requested_model) {
-                    let (model_path, model_variant) = requested_model.clone();
-                    let model_id = manager_guard
-                        .load_model(
-                            model_path.clone(),
-                            model_variant.clone(),
-                            Backend::ONNX,
-                            self.token.clone(),
-                        )
-                        .await
-                        .unwrap();
-                    lookup_guard.insert(requested_model, model_id);
-                }
+        // pin the model to a commit so that the collection is always queried with the same model
+        if config.model_name.starts_with("hf://") && config.model_revision.is_none() {
+            if is_offline() {
+                warn!(
+                    "Cannot resolve the revision of {} in offline mode, the model will not be pinned",
+                    config.model_name
+                );
+            } else {
+                let revision =
+                    resolve_model_revision(config.model_name.as_str(), self.token.clone()).await?;
+                info!(
+                    "Model {} resolved to revision {}",
+                    config.model_name, revision
+                );
+                config.model_revision = Some(revision);
             }
         }
 
+        let name = config.name.clone();
+        let collection = Arc::new(RwLock::new(Collection::new(config, overwrite).await?));
+        let requested_models = collection.read().await.requested_models().await;
+        self.load_models(requested_models).await?;
+
         let mut collections = self.collections.write().await;
         collections.insert(name.clone(), collection.clone());
 
         Ok(())
     }
 
+    /// Loads a loaded collection again from disk, e.g., after it's re-indexed.
+    /// The new one replaces it once it's ready, and searches already running finish on the old one.
+    pub async fn reload_collection(&self, name: &str) -> anyhow::Result<()> {
+        if !self.collections.read().await.contains_key(name) {
+            return Err(anyhow::anyhow!("Collection '{}' is not loaded", name));
+        }
+
+        // the collection is swapped only once it's loaded with all its indexes and models,
+        // so that a failed reload keeps serving the previous one
+        let collection = self.open_collection(name.to_string()).await?;
+        collection.read().await.check_indexes().await?;
+        self.collections
+            .write()
+            .await
+            .insert(name.to_string(), collection);
+        self.unload_unused_models().await;
+        info!("Collection '{}' reloaded", name);
+
+        Ok(())
+    }
+
+    /// Removes a loaded collection from this manager. Its files are left as is.
+    pub async fn unload_collection(&self, name: &str) -> anyhow::Result<()> {
+        self.collections
+            .write()
+            .await
+            .remove(name)
+            .ok_or_else(|| anyhow::anyhow!("Collection '{}' is not loaded", name))?;
+
+        Ok(())
+    }
+
+    /// Refuses to modify a collection loaded by this manager unless `force` is set,
+    /// in which case it's unloaded first.
+    async fn unload_for_modification(&self, name: &str, force: bool) -> anyhow::Result<()> {
+        let mut collections = self.collections.write().await;
+        if collections.contains_key(name) {
+            if !force {
+                return Err(anyhow::anyhow!(
+                    "Collection '{}' is currently loaded. Unload it first or use force",
+                    name
+                ));
+            }
+            collections.remove(name);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a collection from disk.
+    /// Collections that are loaded or served by another process are deleted only if `force` is set.
+    pub async fn delete_collection(&self, name: &str, force: bool) -> anyhow::Result<()> {
+        self.unload_for_modification(name, force).await?;
+        Collection::delete(name, force)
+    }
+
+    /// Renames a collection on disk, including its DuckDB table.
+    /// Collections that are loaded or served by another process are renamed only if `force` is set.
+    pub async fn rename_collection(
+        &self,
+        name: &str,
+        new_name: &str,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        self.unload_for_modification(name, force).await?;
+        Collection::rename(name, new_name, force)
+    }
+
+    /// Copies a collection on disk to a new collection named `new_name`.
+    /// Collections that are served by another process are copied only if `force` is set.
+    pub async fn copy_collection(
+        &self,
+        name: &str,
+        new_name: &str,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        Collection::copy(name, new_name, force)
+    }
+
     pub async fn get_collections(&self) -> Vec<String> {
         let collections = self.collections.read().await;
         let collection_names: Vec<String> = collections.keys().cloned().collect();
@@ -190,7 +344,7 @@ impl CollectionManager {
 
         // Fetch model ID
         let config = collection.read().await.config();
-        let model = (config.model_name, config.model_variant);
+        let model = (config.pinned_model_name(), config.model_variant);
 
         let model_id = self
             .model_lookup
@@ -212,24 +366,142 @@ impl CollectionManager {
             .await
     }
 
+    pub async fn get_collection_stats(
+        &self,
+        collection_name: String,
+    ) -> anyhow::Result<CollectionStats> {
+        let collection = self
+            .collections
+            .read()
+            .await
+            .get(collection_name.as_str())
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Collection '{}' does not exist", collection_name))?;
+
+        let stats = collection.read().await.stats().await?;
+        Ok(stats)
+    }
+
+    /// Fetches `columns` of the rows with the given `keys` from a loaded collection, keyed by `_key`.
+    pub async fn get_rows(
+        &self,
+        collection_name: &str,
+        keys: &[u64],
+        columns: &[String],
+    ) -> anyhow::Result<HashMap<u64, serde_json::Map<String, serde_json::Value>>> {
+        let collection = self
+            .collections
+            .read()
+            .await
+            .get(collection_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Collection '{}' does not exist", collection_name))?;
+
+        let rows = collection.read().await.get_rows(keys, columns).await?;
+        Ok(rows)
+    }
+
+    /// Resolves `model` to one of the loaded models and returns its name and id.
+    /// See `model_name_matches` for the accepted names. A model loaded in several variants
+    /// is selected with `<model>:<variant>`.
+    pub async fn find_model(&self, model: &str) -> anyhow::Result<(String, u32)> {
+        let lookup = self.model_lookup.read().await;
+        let mut candidates: Vec<(&(String, String), &u32)> = lookup
+            .iter()
+            .filter(|((name, _), _)| model_name_matches(model, name))
+            .collect();
+        if candidates.is_empty() {
+            if let Some((name, variant)) = model.rsplit_once(':') {
+                candidates = lookup
+                    .iter()
+                    .filter(|((n, v), _)| v == variant && model_name_matches(name, n))
+                    .collect();
+            }
+        }
+
+        match candidates.as_slice() {
+            [((name, _), model_id)] => Ok((name.to_string(), **model_id)),
+            [] => {
+                let mut loaded: Vec<String> = lookup
+                    .keys()
+                    .map(|(name, variant)| format!("{}:{}", name, variant))
+                    .collect();
+                loaded.sort();
+                Err(anyhow::anyhow!(
+                    "Model '{}' is not loaded. Loaded models: {}",
+                    model,
+                    loaded.join(", ")
+                ))
+            }
+            _ => Err(anyhow::anyhow!(
+                "Model '{}' is loaded in several variants. Select one with {}:<variant>",
+                model,
+                model
+            )),
+        }
+    }
+
+    /// Number of dimensions of the embeddings of a loaded model.
+    pub async fn output_dim(&self, model: &str) -> anyhow::Result<usize> {
+        let _in_use = self.models_in_use.read().await;
+        let (_, model_id) = self.find_model(model).await?;
+        let output_dim = self.model_manager.read().await.output_dim(model_id).await?;
+        Ok(output_dim as usize)
+    }
+
+    /// Embeds `texts` with one of the models loaded for the collections.
+    pub async fn embed(&self, model: &str, texts: Vec<&str>) -> anyhow::Result<TextEmbeddings> {
+        let _in_use = self.models_in_use.read().await;
+        let (model_name, model_id) = self.find_model(model).await?;
+        let model_manager = self.model_manager.read().await;
+
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH_SIZE) {
+            match model_manager.predict(model_id, batch.to_vec()).await? {
+                Embeddings::F16(emb) => embeddings.extend(
+                    emb.rows()
+                        .into_iter()
+                        .map(|row| row.iter().map(|v| v.to_f32()).collect::<Vec<f32>>()),
+                ),
+                Embeddings::F32(emb) => {
+                    embeddings.extend(emb.rows().into_iter().map(|row| row.to_vec()))
+                }
+            }
+        }
+        let token_count = model_manager
+            .count_tokens(model_id, texts)
+            .await?
+            .iter()
+            .sum();
+
+        Ok(TextEmbeddings {
+            model: model_name,
+            embeddings,
+            token_count,
+        })
+    }
+
     pub async fn search(
         &self,
         collection_name: String,
-        column_name: String,
         query: String,
-        limit: u32,
-    ) -> anyhow::Result<Vec<SearchResult>> {
+        options: SearchOptions,
+    ) -> Result<Vec<SearchResult>, SearchError> {
+        let _in_use = self.models_in_use.read().await;
         let collection = self
             .collections
             .read()
             .await
             .get(collection_name.as_str())
             .cloned()
-            .ok_or_else(|| {
-                return anyhow::anyhow!("Collection '{}' does not exist", collection_name);
-            })?;
-        let config = collection.read().await.config();
-        let model = (config.model_name, config.model_variant);
+            .ok_or(SearchError::CollectionNotFound(collection_name))?;
+        let collection = collection.read().await;
+        collection
+            .check_search_options(&options)
+            .await
+            .map_err(SearchError::InvalidOptions)?;
+        let config = collection.config();
+        let model = (config.pinned_model_name(), config.model_variant);
 
         let model_id = self
             .model_lookup
@@ -238,34 +510,38 @@ impl CollectionManager {
             .get(&model)
             .copied()
             .ok_or_else(|| {
-                return anyhow::anyhow!(
+                SearchError::Failed(anyhow::anyhow!(
                     "Model requested by collection is not loaded. This should never happen"
-                );
+                ))
             })?;
 
-        let results = collection
-            .read()
+        collection
+            .search(query, &options, self.model_manager.clone(), model_id)
             .await
-            .search(
-                column_name,
-                query,
-                limit,
-                self.model_manager.clone(),
-                model_id,
-            )
-            .await?;
-
-        Ok(results)
+            .map_err(SearchError::Failed)
     }
 }
 
+/// Whether `requested` names the loaded model `loaded`, which may be pinned to a revision
+/// such as `hf://mys/minilm@<commit>`. The `hf://` prefix and the revision can be omitted.
+fn model_name_matches(requested: &str, loaded: &str) -> bool {
+    let requested = requested.strip_prefix("hf://").unwrap_or(requested);
+    let loaded = loaded.strip_prefix("hf://").unwrap_or(loaded);
+    requested == loaded || loaded.split('@').next() == Some(requested)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{model_name_matches, CollectionManager, SearchError};
+    use crate::collection::collection_type::Collection;
+    use crate::collection::collection_utils::{
+        collection_dir, remove_server_locks, server_lock_holder, CollectionConfig, SearchColumn,
+        SearchOptions, ServerLock,
+    };
+    use crate::model::model_cache::remove_cached_model;
+    use std::collections::BTreeMap;
     use std::fs;
 
-    use super::CollectionManager;
-    use crate::collection::collection_utils::{home_dir, CollectionConfig};
-
     #[tokio::test]
     async fn test_collection_manager() {
         let manager = CollectionManager::new(None);
@@ -285,7 +561,10 @@ mod tests {
             vec!["test_collection".to_string()]
         );
 
-        assert_eq!(manager.get_collection_configs().await[0], config);
+        let created_config = manager.get_collection_configs().await[0].clone();
+        assert!(created_config.model_revision.is_some());
+        config.model_revision = created_config.model_revision.clone();
+        assert_eq!(created_config, config);
         manager
             .import_jsonl(
                 "test_collection",
@@ -303,17 +582,227 @@ mod tests {
 
         // Search
         let query = "What is the total amount of the invoice?".to_string();
+        let mut options = SearchOptions::default();
+        options.columns = vec![SearchColumn::new(column_name)];
+        let results = manager
+            .search("test_collection".to_string(), query, options)
+            .await
+            .unwrap();
+        assert!(!results.is_empty()); // This might not always be true, depending on the data and query
+        assert_eq!(results[0].matched_columns, vec![column_name.to_string()]);
+
+        // requests that can't be served are told apart from failing searches
+        let search = |collection: &str, options: SearchOptions| {
+            manager.search(collection.to_string(), String::from("invoice"), options)
+        };
+        let options = SearchOptions {
+            columns: vec![SearchColumn::new(column_name)],
+            ..Default::default()
+        };
+        assert!(matches!(
+            search("missing_collection", options.clone()).await,
+            Err(SearchError::CollectionNotFound(_))
+        ));
+        assert!(matches!(
+            search(
+                "test_collection",
+                SearchOptions {
+                    columns: vec![SearchColumn::new("not_indexed")],
+                    ..Default::default()
+                }
+            )
+            .await,
+            Err(SearchError::InvalidOptions(_))
+        ));
+        assert!(matches!(
+            search(
+                "test_collection",
+                SearchOptions {
+                    filters: BTreeMap::from([(String::from("missing"), serde_json::json!(1))]),
+                    ..options
+                }
+            )
+            .await,
+            Err(SearchError::InvalidOptions(_))
+        ));
+
+        // Reloading keeps the collection searchable
+        manager.reload_collection("test_collection").await.unwrap();
         let results = manager
             .search(
                 "test_collection".to_string(),
-                column_name.to_string(),
-                query,
-                10,
+                "What is the total amount of the invoice?".to_string(),
+                SearchOptions {
+                    columns: vec![SearchColumn::new(column_name)],
+                    ..Default::default()
+                },
             )
             .await
             .unwrap();
-        assert!(!results.is_empty()); // This might not always be true, depending on the data and query
+        assert!(!results.is_empty());
+
+        // Reloading while a search is running doesn't unload the model it uses
+        let (results, reloaded) = tokio::join!(
+            manager.search(
+                "test_collection".to_string(),
+                "What is the total amount of the invoice?".to_string(),
+                SearchOptions {
+                    columns: vec![SearchColumn::new(column_name)],
+                    ..Default::default()
+                },
+            ),
+            manager.reload_collection("test_collection")
+        );
+        assert!(!results.unwrap().is_empty());
+        reloaded.unwrap();
+
+        // Embed texts with the model loaded for the collection
+        let embeddings = manager
+            .embed("mys/minilm", vec!["first text", "second text"])
+            .await
+            .unwrap();
+        assert_eq!(embeddings.embeddings.len(), 2);
+        assert!(embeddings.model.starts_with("hf://mys/minilm@"));
+        assert!(embeddings.token_count >= 4);
+        assert!(manager.embed("mys/other", vec!["text"]).await.is_err());
 
-        fs::remove_dir_all(home_dir().join("models").join("mys").join("minilm")).unwrap();
+        remove_cached_model("hf://mys/minilm", true).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unload_waits_for_requests() {
+        let manager = CollectionManager::new(None);
+        manager
+            .model_lookup
+            .write()
+            .await
+            .insert((String::from("unused"), String::from("f32")), 42);
+
+        let in_use = manager.models_in_use.read().await;
+        let unloaded = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            manager.unload_unused_models(),
+        )
+        .await;
+        assert!(unloaded.is_err());
+        assert_eq!(manager.model_lookup.read().await.len(), 1);
+
+        drop(in_use);
+        manager.unload_unused_models().await;
+        assert!(manager.model_lookup.read().await.is_empty());
+    }
+
+    #[test]
+    fn test_model_name_matches() {
+        assert!(model_name_matches("hf://mys/minilm", "hf://mys/minilm@abc"));
+        assert!(model_name_matches("mys/minilm", "hf://mys/minilm@abc"));
+        assert!(model_name_matches("mys/minilm@abc", "hf://mys/minilm@abc"));
+        assert!(!model_name_matches("mys/minilm@def", "hf://mys/minilm@abc"));
+        assert!(!model_name_matches("mys/mini", "hf://mys/minilm@abc"));
+        assert!(model_name_matches("/models/minilm", "/models/minilm"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_rename_copy_collection() {
+        for name in ["test_manage", "test_manage_copy", "test_manage_renamed"] {
+            let _ = fs::remove_dir_all(collection_dir(name));
+        }
+
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_manage");
+        let jsonl_path = std::env::temp_dir().join("test_manage.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"text\": \"first\"}\n{\"text\": \"second\"}\n",
+        )
+        .unwrap();
+        {
+            let collection = Collection::new(config, true).await.unwrap();
+            collection
+                .import_jsonl(jsonl_path.to_str().unwrap())
+                .await
+                .unwrap();
+        }
+
+        let manager = CollectionManager::new(None);
+        assert!(manager.reload_collection("test_manage").await.is_err());
+        manager
+            .copy_collection("test_manage", "test_manage_copy", false)
+            .await
+            .unwrap();
+        assert!(manager
+            .copy_collection("test_manage", "test_manage_copy", false)
+            .await
+            .is_err());
+        assert!(manager
+            .copy_collection("test_manage", "1invalid", false)
+            .await
+            .is_err());
+
+        // names of existing collections are validated too, so they can't point outside of the home dir
+        assert!(manager
+            .delete_collection("../test_manage", true)
+            .await
+            .is_err());
+        assert!(manager
+            .rename_collection("../test_manage", "test_manage_other", true)
+            .await
+            .is_err());
+        assert!(manager
+            .copy_collection("../test_manage", "test_manage_other", true)
+            .await
+            .is_err());
+
+        manager
+            .rename_collection("test_manage_copy", "test_manage_renamed", false)
+            .await
+            .unwrap();
+        assert!(!collection_dir("test_manage_copy").exists());
+        let renamed = Collection::from("test_manage_renamed".to_string())
+            .await
+            .unwrap();
+        let stats = renamed.stats().await.unwrap();
+        assert_eq!(stats.name, "test_manage_renamed");
+        assert_eq!(stats.row_count, 2);
+        drop(renamed);
+
+        // a served collection can only be modified with force
+        let lock = ServerLock::acquire("test_manage_renamed").unwrap();
+        assert!(manager
+            .delete_collection("test_manage_renamed", false)
+            .await
+            .is_err());
+        assert!(manager
+            .rename_collection("test_manage_renamed", "test_manage_copy", false)
+            .await
+            .is_err());
+        assert!(manager
+            .copy_collection("test_manage_renamed", "test_manage_copy", false)
+            .await
+            .is_err());
+        // the lock file is written again after re-indexing removed it
+        remove_server_locks(&collection_dir("test_manage_renamed")).unwrap();
+        assert!(server_lock_holder("test_manage_renamed").is_none());
+        lock.refresh().unwrap();
+        assert_eq!(
+            server_lock_holder("test_manage_renamed"),
+            Some(std::process::id())
+        );
+        drop(lock);
+        manager
+            .delete_collection("test_manage_renamed", false)
+            .await
+            .unwrap();
+        assert!(!collection_dir("test_manage_renamed").exists());
+
+        manager
+            .delete_collection("test_manage", true)
+            .await
+            .unwrap();
+        assert!(manager
+            .delete_collection("test_manage", true)
+            .await
+            .is_err());
+        fs::remove_file(jsonl_path).unwrap();
     }
 }
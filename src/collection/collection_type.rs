@@ -1,34 +1,45 @@
 use super::collection_utils::SearchResult;
-use crate::collection::collection_utils::{home_dir, CollectionConfig};
-use crate::collection::vector_index::VectorIndex;
+use crate::collection::collection_utils::{
+    collection_dir, copy_dir, dir_size, filter_matches, home_dir, parse_sql_expression,
+    parse_template, quote_identifier, quote_literal, remove_server_locks, server_lock_holder,
+    validate_collection_name, value_to_json, CollectionConfig, CollectionStats, ColumnInfo,
+    ExportContent, ExportFormat, FusionMethod, IndexedColumnStats, ListMode, SearchColumn,
+    SearchOptions, TemplatePart, VirtualField, CANDIDATES_PER_RESULT, MAX_FILTERED_CANDIDATES,
+};
+use crate::collection::migrations::check_serialization_version;
+use crate::collection::vector_index::{SimilarityResult, VectorIndex};
+use crate::hf_ops::resolve_dataset_path;
 use crate::model::model_manager::ModelManager;
 use crate::model::model_utils::{Embeddings, ModelOutputDType};
 use anyhow::Error;
-use duckdb::arrow::array::{PrimitiveArray, StringArray};
-use duckdb::arrow::datatypes::UInt64Type;
-use duckdb::arrow::record_batch::RecordBatch;
-use duckdb::Connection;
-use log::{debug, info};
+use duckdb::types::Value;
+use duckdb::{params, params_from_iter, Connection};
+use log::{debug, info, warn};
 use serde_json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use usearch::f16 as UsearchF16;
 use usearch::{IndexOptions, MetricKind, ScalarKind};
 
+/// Constant of reciprocal rank fusion that dampens the weight of the top ranks.
+const RRF_K: f32 = 60.0;
+
 pub struct Collection {
     config: CollectionConfig,
     // TODO: is it really necessary to acquire a lock on this? duckdb seems to be thread-safe itself.
-    conn: Arc<RwLock<Connection>>,
+    conn: Arc<Mutex<Connection>>,
     vector_index: RwLock<HashMap<String, Arc<RwLock<VectorIndex>>>>,
 }
 
 impl Collection {
     pub async fn new(config: CollectionConfig, overwrite: bool) -> anyhow::Result<Self> {
         debug!("creating new Collection instance");
+        validate_collection_name(config.name.as_str())?;
         let name = config.name.as_str();
         let collection_dir = home_dir().join("collections").join(name);
         let collection_dir_str = collection_dir.to_str().unwrap();
@@ -51,7 +62,7 @@ impl Collection {
 
         Ok(Collection {
             config: config,
-            conn: Arc::new(RwLock::new(conn)),
+            conn: Arc::new(Mutex::new(conn)),
             vector_index: RwLock::new(HashMap::new()),
         })
     }
@@ -59,7 +70,7 @@ impl Collection {
     pub async fn from(name: String) -> anyhow::Result<Self> {
         let collection_dir = home_dir().join("collections").join(name.as_str());
         if !collection_dir.exists() {
-            return Err(Error::msg("Collection {name} does not exist"));
+            return Err(anyhow::anyhow!("Collection '{}' does not exist", name));
         }
 
         let config_path = collection_dir.join("config.json");
@@ -69,6 +80,7 @@ impl Collection {
 
         let config_file = File::open(config_path).unwrap();
         let config: CollectionConfig = serde_json::from_reader(config_file)?;
+        check_serialization_version(name.as_str(), config.serialization_version)?;
         let conn = Connection::open(collection_dir.join(config.db_path.as_str()))?;
 
         let vector_indexes = RwLock::new(HashMap::new());
@@ -87,7 +99,7 @@ impl Collection {
 
         Ok(Collection {
             config: config,
-            conn: Arc::new(RwLock::new(conn)),
+            conn: Arc::new(Mutex::new(conn)),
             vector_index: vector_indexes,
         })
     }
@@ -96,17 +108,403 @@ impl Collection {
         self.config.clone()
     }
 
+    /// Errors if an indexed column of the config has no loaded index,
+    /// e.g. when the collection is read while being re-indexed.
+    pub async fn check_indexes(&self) -> anyhow::Result<()> {
+        let indexes = self.vector_index.read().await;
+        let missing: Vec<&str> = self
+            .config
+            .index_columns
+            .iter()
+            .filter(|column| !indexes.contains_key(column.as_str()))
+            .map(|column| column.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Collection '{}' has no index for {}",
+                self.config.name,
+                missing.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the columns `options` refer to can be searched, filtered and collapsed on.
+    pub async fn check_search_options(&self, options: &SearchOptions) -> anyhow::Result<()> {
+        options.validate()?;
+        {
+            let indexes = self.vector_index.read().await;
+            if let Some(column) = options
+                .columns
+                .iter()
+                .find(|column| !indexes.contains_key(column.column_name.as_str()))
+            {
+                return Err(anyhow::anyhow!(
+                    "Column '{}' is not indexed in collection '{}'",
+                    column.column_name,
+                    self.config.name
+                ));
+            }
+        }
+        for column in options.filters.keys().chain(options.collapse_column.iter()) {
+            if !self.is_virtual_field(column) {
+                self.check_column(column).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Quoted name of the collection's table to use in queries.
+    fn table(&self) -> String {
+        quote_identifier(self.config.name.as_str())
+    }
+
+    fn is_virtual_field(&self, name: &str) -> bool {
+        self.config
+            .virtual_fields
+            .iter()
+            .any(|field| field.name == name)
+    }
+
+    /// Checks that `column` exists in the collection's table before it's used in a query.
+    pub async fn check_column(&self, column: &str) -> anyhow::Result<()> {
+        let schema = self.schema().await?;
+        if !schema.iter().any(|c| c.name == column) {
+            return Err(anyhow::anyhow!(
+                "Column '{}' does not exist in collection '{}'",
+                column,
+                self.config.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn read_config(name: &str) -> anyhow::Result<CollectionConfig> {
+        let config_path = collection_dir(name).join("config.json");
+        if !config_path.exists() {
+            return Err(anyhow::anyhow!("Collection '{}' does not exist", name));
+        }
+
+        let config: CollectionConfig = serde_json::from_reader(File::open(config_path)?)?;
+        Ok(config)
+    }
+
+    /// Renames the DuckDB table of a collection on disk and updates its config accordingly.
+    /// The collection must not be open.
+    fn rename_table(name: &str, new_name: &str, dir: &Path) -> anyhow::Result<()> {
+        let config_path = dir.join("config.json");
+        let mut config: CollectionConfig = serde_json::from_reader(File::open(&config_path)?)?;
+        {
+            let conn = Connection::open(dir.join(config.db_path.as_str()))?;
+            let table_exists: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_name = ?;",
+                [name],
+                |row| row.get(0),
+            )?;
+            if table_exists > 0 {
+                conn.execute_batch(
+                    format!(
+                        "ALTER TABLE {} RENAME TO {};",
+                        quote_identifier(name),
+                        quote_identifier(new_name)
+                    )
+                    .as_str(),
+                )?;
+            }
+        }
+
+        config.name = new_name.to_string();
+        serde_json::to_writer(File::create(config_path)?, &config)?;
+        Ok(())
+    }
+
+    fn check_not_served(name: &str, force: bool) -> anyhow::Result<()> {
+        if let Some(pid) = server_lock_holder(name) {
+            if !force {
+                return Err(anyhow::anyhow!(
+                    "Collection '{}' is currently being served by process {}. Stop the server first or use force",
+                    name,
+                    pid
+                ));
+            }
+            warn!(
+                "Collection '{}' is being served by process {}, proceeding anyway",
+                name, pid
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the collection `name` from disk.
+    /// It refuses to delete a collection that is being served unless `force` is set.
+    pub fn delete(name: &str, force: bool) -> anyhow::Result<()> {
+        validate_collection_name(name)?;
+        Self::read_config(name)?;
+        Self::check_not_served(name, force)?;
+
+        fs::remove_dir_all(collection_dir(name))?;
+        info!("Collection '{}' deleted", name);
+        Ok(())
+    }
+
+    /// Renames the collection `name` on disk to `new_name`, including its DuckDB table.
+    /// It refuses to rename a collection that is being served unless `force` is set.
+    pub fn rename(name: &str, new_name: &str, force: bool) -> anyhow::Result<()> {
+        validate_collection_name(name)?;
+        validate_collection_name(new_name)?;
+        Self::read_config(name)?;
+        Self::check_not_served(name, force)?;
+        let new_dir = collection_dir(new_name);
+        if new_dir.exists() {
+            return Err(anyhow::anyhow!("Collection '{}' already exists", new_name));
+        }
+
+        fs::rename(collection_dir(name), &new_dir)?;
+        if let Err(e) = Self::rename_table(name, new_name, &new_dir) {
+            // leave the collection as it was before
+            fs::rename(&new_dir, collection_dir(name))?;
+            return Err(e);
+        }
+
+        info!("Collection '{}' renamed to '{}'", name, new_name);
+        Ok(())
+    }
+
+    /// Copies the collection `name` to a new collection named `new_name`.
+    /// It refuses to copy a collection that is being served unless `force` is set,
+    /// as the copy may catch its files in the middle of a write.
+    pub fn copy(name: &str, new_name: &str, force: bool) -> anyhow::Result<()> {
+        validate_collection_name(name)?;
+        validate_collection_name(new_name)?;
+        Self::read_config(name)?;
+        Self::check_not_served(name, force)?;
+        let new_dir = collection_dir(new_name);
+        if new_dir.exists() {
+            return Err(anyhow::anyhow!("Collection '{}' already exists", new_name));
+        }
+
+        let result = copy_dir(&collection_dir(name), &new_dir)
+            .and_then(|_| remove_server_locks(&new_dir))
+            .and_then(|_| Self::rename_table(name, new_name, &new_dir));
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&new_dir);
+            return Err(e);
+        }
+
+        info!("Collection '{}' copied to '{}'", name, new_name);
+        Ok(())
+    }
+
+    /// Columns of the collection's table in order. It's empty if nothing has been imported yet.
+    pub async fn schema(&self) -> anyhow::Result<Vec<ColumnInfo>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(
+            "SELECT column_name, data_type FROM information_schema.columns WHERE table_name = ? ORDER BY ordinal_position;",
+        )?;
+        let columns = stmt
+            .query_map([self.config.name.as_str()], |row| {
+                Ok(ColumnInfo {
+                    name: row.get(0)?,
+                    data_type: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(columns)
+    }
+
+    pub async fn stats(&self) -> anyhow::Result<CollectionStats> {
+        let schema = self.schema().await?;
+        let row_count: u64 = if schema.is_empty() {
+            0
+        } else {
+            let conn_guard = self.conn.lock().await;
+            let query = format!("SELECT COUNT(*) FROM {};", self.table());
+            let count: i64 = conn_guard.query_row(&query, [], |row| row.get(0))?;
+            count as u64
+        };
+
+        let mut indexes = Vec::new();
+        for (column, index) in self.vector_index.read().await.iter() {
+            indexes.push(IndexedColumnStats {
+                column: column.clone(),
+                index: index.read().await.stats()?,
+            });
+        }
+        indexes.sort_by(|a, b| a.column.cmp(&b.column));
+
+        let dir = collection_dir(self.config.name.as_str());
+        Ok(CollectionStats {
+            name: self.config.name.clone(),
+            row_count,
+            schema,
+            indexes,
+            model_name: self.config.model_name.clone(),
+            model_variant: self.config.model_variant.clone(),
+            model_revision: self.config.model_revision.clone(),
+            db_size: dir_size(&dir.join(self.config.db_path.as_str())),
+            index_size: dir_size(&dir.join(self.config.index_dir.as_str())),
+            total_size: dir_size(&dir),
+            serialization_version: self.config.serialization_version,
+        })
+    }
+
+    /// Writes the embeddings of `column` into a temporary table `_export_<column>` to join them with the collection's table.
+    async fn write_vectors_to_temp_table(
+        &self,
+        conn: &Connection,
+        column: &str,
+        keys: &[u64],
+    ) -> anyhow::Result<()> {
+        let indexes_guard = self.vector_index.read().await;
+        let index = indexes_guard.get(column).unwrap().read().await;
+        let temp_table = quote_identifier(format!("_export_{}", column).as_str());
+        conn.execute_batch(
+            format!(
+                "CREATE OR REPLACE TEMP TABLE {} (_key UBIGINT, {} FLOAT[]);",
+                temp_table,
+                quote_identifier(format!("{}_embedding", column).as_str())
+            )
+            .as_str(),
+        )?;
+
+        // vectors are bound as parameters rather than formatted into the query,
+        // so that NaN and infinite components survive the round trip
+        for key in keys {
+            if let Some(vector) = index.get_vector(*key)? {
+                let query = format!(
+                    "INSERT INTO {} VALUES (?, [{}]);",
+                    temp_table,
+                    vec!["?"; vector.len()].join(", ")
+                );
+                let params = std::iter::once(Value::UBigInt(*key))
+                    .chain(vector.into_iter().map(Value::Float));
+                conn.prepare_cached(&query)?
+                    .execute(params_from_iter(params))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Exports the collection to a Parquet or JSONL file at `path`.
+    /// Embeddings are read back from the vector indexes by `_key` and written to `<column>_embedding` columns.
+    /// Returns the number of exported rows.
+    pub async fn export(
+        &self,
+        path: &str,
+        format: ExportFormat,
+        content: ExportContent,
+    ) -> anyhow::Result<usize> {
+        let start = Instant::now();
+        let schema = self.schema().await?;
+        if schema.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Collection '{}' has no data to export",
+                self.config.name
+            ));
+        }
+
+        let mut columns: Vec<String> = self.vector_index.read().await.keys().cloned().collect();
+        columns.sort();
+        if content == ExportContent::VectorsOnly && columns.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Collection '{}' has no indexed columns to export vectors from",
+                self.config.name
+            ));
+        }
+
+        // temporary tables are visible to the whole connection, so no other query should run meanwhile
+        let conn = self.conn.clone();
+        let conn_guard = conn.lock().await;
+        let mut projection = match content {
+            ExportContent::VectorsOnly => vec![String::from("t._key")],
+            _ => vec![String::from("t.*")],
+        };
+        let mut joins = Vec::new();
+        if content != ExportContent::MetadataOnly {
+            let keys: Vec<u64> = {
+                let mut stmt = conn_guard.prepare(
+                    format!("SELECT _key FROM {} ORDER BY _key;", self.table()).as_str(),
+                )?;
+                let keys = stmt
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<Vec<u64>, _>>()?;
+                keys
+            };
+
+            for column in columns.iter() {
+                let embedding_column = format!("{}_embedding", column);
+                if schema.iter().any(|c| c.name == embedding_column) {
+                    return Err(anyhow::anyhow!(
+                        "Column '{}' already exists in collection '{}'",
+                        embedding_column,
+                        self.config.name
+                    ));
+                }
+
+                self.write_vectors_to_temp_table(&conn_guard, column, &keys)
+                    .await?;
+                let temp_table = quote_identifier(format!("_export_{}", column).as_str());
+                projection.push(format!(
+                    "{}.{}",
+                    temp_table,
+                    quote_identifier(embedding_column.as_str())
+                ));
+                joins.push(format!(
+                    "LEFT JOIN {} ON t._key = {}._key",
+                    temp_table, temp_table
+                ));
+            }
+        }
+
+        let format_option = match format {
+            ExportFormat::Parquet => "FORMAT PARQUET",
+            ExportFormat::Jsonl => "FORMAT JSON",
+        };
+        let query = format!(
+            "COPY (SELECT {} FROM {} t {} ORDER BY t._key) TO {} ({});",
+            projection.join(", "),
+            self.table(),
+            joins.join(" "),
+            quote_literal(path),
+            format_option
+        );
+        let result = conn_guard.execute(query.as_str(), []);
+
+        for column in columns.iter() {
+            let temp_table = quote_identifier(format!("_export_{}", column).as_str());
+            conn_guard.execute_batch(format!("DROP TABLE IF EXISTS {};", temp_table).as_str())?;
+        }
+
+        let row_count = result?;
+        info!(
+            "{} rows exported to {} in {:?}",
+            row_count,
+            path,
+            start.elapsed()
+        );
+
+        Ok(row_count)
+    }
+
     pub async fn import_jsonl(&self, jsonl_path: &str) -> anyhow::Result<()> {
         let start = Instant::now();
+        let jsonl_path = resolve_dataset_path(jsonl_path)?;
         // prevent deadlock when add_keys_to_db is trying to acquire a lock
         {
             let conn = self.conn.clone();
-            let mut conn_guard = conn.write().await;
+            let mut conn_guard = conn.lock().await;
             let tx = conn_guard.transaction()?;
             tx.execute_batch(
                 format!(
-                    "CREATE TABLE {} AS SELECT * FROM read_json_auto('{}');",
-                    &self.config.name, jsonl_path
+                    "CREATE TABLE {} AS SELECT * FROM read_json_auto({});",
+                    self.table(),
+                    quote_literal(jsonl_path.as_str())
                 )
                 .as_str(),
             )?;
@@ -126,16 +524,18 @@ impl Collection {
 
     pub async fn import_parquet(&self, parquet_path: &str) -> anyhow::Result<()> {
         let start = Instant::now();
+        let parquet_path = resolve_dataset_path(parquet_path)?;
         // prevent deadlock when add_keys_to_db is trying to acquire a lock
         {
             let conn = self.conn.clone();
-            let mut conn_guard = conn.write().await;
+            let mut conn_guard = conn.lock().await;
             let tx = conn_guard.transaction()?;
 
             tx.execute_batch(
                 format!(
-                    "CREATE TABLE {} AS SELECT * FROM read_parquet('{}', filename = true);",
-                    &self.config.name, parquet_path
+                    "CREATE TABLE {} AS SELECT * FROM read_parquet({}, filename = true);",
+                    self.table(),
+                    quote_literal(parquet_path.as_str())
                 )
                 .as_str(),
             )?;
@@ -152,6 +552,8 @@ impl Collection {
         Ok(())
     }
 
+    /// Returns the texts of `column` as they are embedded, with list elements joined.
+    /// If `keys` is given, only the values of those rows are returned in the order of `keys`.
     pub async fn get_single_column(
         &self,
         column_name: &str,
@@ -160,42 +562,86 @@ impl Collection {
         keys: Vec<u64>,
     ) -> anyhow::Result<Vec<String>> {
         assert!(limit >= 1);
-        let conn = self.conn.clone();
-        let conn_guard = conn.read().await;
+        let (expression, _) = self.text_expression(column_name, ListMode::Join).await?;
+        let conn_guard = self.conn.lock().await;
         let query = if keys.is_empty() {
             format!(
-                "SELECT {} FROM {} LIMIT {} OFFSET {};",
-                column_name, &self.config.name, limit, offset
+                "SELECT _key, COALESCE({}, '') FROM {} ORDER BY _key LIMIT ? OFFSET ?;",
+                expression,
+                self.table()
             )
         } else {
-            let keys_str = keys
-                .iter()
-                .map(|key| key.to_string())
-                .collect::<Vec<_>>()
-                .join(", ");
             format!(
-                "SELECT {} FROM {} WHERE _key IN ({}) LIMIT {} OFFSET {};",
-                column_name, &self.config.name, &keys_str, limit, offset,
+                "SELECT _key, COALESCE({}, '') FROM {} WHERE _key IN ({}) ORDER BY _key LIMIT ? OFFSET ?;",
+                expression,
+                self.table(),
+                vec!["?"; keys.len()].join(", ")
             )
         };
 
         let mut stmt = conn_guard.prepare(&query)?;
-        let result: Vec<RecordBatch> = stmt.query_arrow([])?.collect();
-        assert_eq!(result.len(), 1);
-        let batch = &result[0];
+        let pagination = [limit, offset];
+        let params = keys.iter().chain(pagination.iter());
+        let rows = stmt
+            .query_map(params_from_iter(params), |row| {
+                Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let col_array = batch
-            .column_by_name(column_name)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<StringArray>()
-            .unwrap();
-        let col_values: Vec<String> = col_array
+        if keys.is_empty() {
+            return Ok(rows.into_iter().map(|(_, value)| value).collect());
+        }
+
+        let values_by_key: HashMap<u64, String> = rows.into_iter().collect();
+        Ok(keys
             .iter()
-            .map(|s| s.unwrap().to_string())
-            .collect::<Vec<String>>();
+            .filter_map(|key| values_by_key.get(key).cloned())
+            .collect())
+    }
+
+    /// Fetches `columns` of the rows with the given `keys`, keyed by `_key`.
+    pub async fn get_rows(
+        &self,
+        keys: &[u64],
+        columns: &[String],
+    ) -> anyhow::Result<HashMap<u64, serde_json::Map<String, serde_json::Value>>> {
+        let mut rows_by_key = HashMap::new();
+        if keys.is_empty() || columns.is_empty() {
+            return Ok(rows_by_key);
+        }
+
+        let mut expressions = Vec::with_capacity(columns.len());
+        for column in columns {
+            if self.is_virtual_field(column) {
+                let (expression, _) = self.text_expression(column, ListMode::Join).await?;
+                expressions.push(expression);
+            } else {
+                self.check_column(column).await?;
+                expressions.push(quote_identifier(column));
+            }
+        }
+
+        let conn_guard = self.conn.lock().await;
+        let query = format!(
+            "SELECT _key, {} FROM {} WHERE _key IN ({});",
+            expressions.join(", "),
+            self.table(),
+            vec!["?"; keys.len()].join(", ")
+        );
+
+        let mut stmt = conn_guard.prepare(&query)?;
+        let mut rows = stmt.query(params_from_iter(keys.iter()))?;
+        while let Some(row) = rows.next()? {
+            let key: u64 = row.get(0)?;
+            let mut fields = serde_json::Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                let value: Value = row.get(i + 1)?;
+                fields.insert(column.clone(), value_to_json(&value));
+            }
+            rows_by_key.insert(key, fields);
+        }
 
-        Ok(col_values)
+        Ok(rows_by_key)
     }
 
     async fn embed_column_with_offset(
@@ -258,14 +704,19 @@ impl Collection {
         model_manager: Arc<RwLock<ModelManager>>,
         model_id: u32,
     ) -> anyhow::Result<()> {
-        let count: u64 = {
-            let conn_guard = self.conn.read().await;
-            let query = format!("SELECT COUNT('{}') FROM {};", column_name, self.config.name);
-            let mut stmt = conn_guard.prepare(&query)?;
-            let count: i64 = stmt.query_row([], |row| row.get(0))?;
-
-            count as u64
-        };
+        let (count, skipped) = self.count_texts(column_name).await?;
+        if skipped > 0 {
+            warn!(
+                "Skipping {} rows with NULL or empty values in column '{}'",
+                skipped, column_name
+            );
+        }
+        if count == 0 {
+            return Err(anyhow::anyhow!(
+                "Column '{}' has no values to embed",
+                column_name
+            ));
+        }
         let num_batches = (count + batch_size - 1) / batch_size;
         info!("Starting to index {count} records from column '{column_name}' in batches of {batch_size}");
 
@@ -359,92 +810,262 @@ impl Collection {
 
     pub async fn requested_models(&self) -> Vec<(String, String)> {
         vec![(
-            self.config.model_name.clone(),
+            self.config.pinned_model_name(),
             self.config.model_variant.clone(),
         )]
     }
 
+    /// Searches the indexed columns in `options` for `query`.
+    /// When several columns are given, results are merged by `_key` with `options.fusion`.
+    /// Candidates are then collapsed by `options.collapse_column` and re-ranked with MMR if requested.
+    /// Hits scoring lower than `options.min_score` or not matching `options.filters` are dropped
+    /// before the page at `options.offset` is taken.
     pub async fn search(
         &self,
-        column_name: String,
         query: String,
-        limit: u32,
+        options: &SearchOptions,
         model_manager: Arc<RwLock<ModelManager>>,
         model_id: u32,
     ) -> anyhow::Result<Vec<SearchResult>> {
+        options.validate()?;
         let texts = vec![query.as_str()];
         let embeddings = model_manager.read().await.predict(model_id, texts).await?;
 
-        let similarity_results = match embeddings {
-            Embeddings::F16(emb) => {
-                let (_, vector_dim) = emb.dim();
-
-                self.vector_index
-                    .read()
-                    .await
-                    .get(column_name.as_str())
-                    .cloned()
-                    .ok_or_else(|| anyhow::anyhow!("Index not found for {}", column_name))?
-                    .read()
-                    .await
-                    .search::<UsearchF16>(
-                        emb.as_ptr() as *const UsearchF16,
-                        vector_dim,
-                        limit as usize,
-                    )
-                    .await?
+        let mut fused_results = self.search_candidates(&embeddings, options).await?;
+        if let Some(collapse_column) = options.collapse_column.as_ref() {
+            fused_results = self
+                .collapse_results(fused_results, collapse_column)
+                .await?;
+        }
+        if let Some(mmr) = options.mmr.as_ref() {
+            let relevance = normalized_scores(&fused_results);
+            let mut candidate_vectors = Vec::with_capacity(fused_results.len());
+            for result in fused_results.iter() {
+                candidate_vectors.push(
+                    self.get_vector(result.matched_columns[0].as_str(), result.key)
+                        .await?,
+                );
             }
-            Embeddings::F32(emb) => {
-                let (_, vector_dim) = emb.dim();
+            let order = mmr_order(
+                &relevance,
+                &candidate_vectors,
+                mmr.lambda,
+                options.window() as usize,
+            );
+            let mut candidates: Vec<Option<FusedResult>> =
+                fused_results.into_iter().map(Some).collect();
+            fused_results = order
+                .into_iter()
+                .filter_map(|i| candidates[i].take())
+                .collect();
+        }
+        let fused_results: Vec<FusedResult> = fused_results
+            .into_iter()
+            .skip(options.offset as usize)
+            .take(options.limit as usize)
+            .collect();
 
-                self.vector_index
-                    .read()
-                    .await
-                    .get(column_name.as_str())
-                    .cloned()
-                    .ok_or_else(|| anyhow::anyhow!("Index not found for {}", column_name))?
-                    .read()
-                    .await
-                    .search::<f32>(emb.as_ptr(), vector_dim, limit as usize)
-                    .await?
+        // contents are read from the column that contributed the most to each hit
+        let mut contents: HashMap<u64, String> = HashMap::new();
+        for column in options.columns.iter() {
+            let keys: Vec<u64> = fused_results
+                .iter()
+                .filter(|r| r.matched_columns[0] == column.column_name)
+                .map(|r| r.key)
+                .collect();
+            if keys.is_empty() {
+                continue;
             }
-        };
-
-        let similar_keys: Vec<u64> = similarity_results.iter().map(|r| r.key).collect();
-        let contents = self
-            .get_single_column(
-                column_name.as_str(),
-                similar_keys.len() as u64,
-                0,
-                similar_keys,
-            )
-            .await?;
+            let column_contents = self
+                .get_single_column(
+                    column.column_name.as_str(),
+                    keys.len() as u64,
+                    0,
+                    keys.clone(),
+                )
+                .await?;
+            contents.extend(keys.into_iter().zip(column_contents));
+        }
 
-        let search_results = similarity_results
-            .iter()
-            .zip(contents.iter())
-            .map(|(result, content)| SearchResult {
-                content: content.to_string(),
+        let search_results = fused_results
+            .into_iter()
+            .map(|result| SearchResult {
+                content: contents.remove(&result.key).unwrap_or_default(),
                 key: result.key,
                 score: result.score,
+                matched_columns: result.matched_columns,
             })
             .collect();
 
         Ok(search_results)
     }
 
+    /// Hits of the indexed columns in `options` for the query `embeddings`, merged by `_key`
+    /// and without those scoring lower than `options.min_score` or not matching `options.filters`.
+    /// With filters, more candidates are fetched until `options.candidate_count()` of them match,
+    /// the indexes are exhausted or `MAX_FILTERED_CANDIDATES` were fetched.
+    async fn search_candidates(
+        &self,
+        embeddings: &Embeddings,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Vec<FusedResult>> {
+        let pool_size = options.candidate_count();
+        let mut candidate_count = pool_size;
+        loop {
+            let mut results_by_column = Vec::with_capacity(options.columns.len());
+            let mut exhausted = true;
+            for column in options.columns.iter() {
+                let similarity_results = self
+                    .search_index(column.column_name.as_str(), embeddings, candidate_count)
+                    .await?;
+                exhausted &= similarity_results.len() < candidate_count as usize;
+                results_by_column.push((column, similarity_results));
+            }
+            let mut fused_results =
+                fuse_results(&results_by_column, options.fusion, candidate_count);
+            if let Some(min_score) = options.min_score {
+                let fused_count = fused_results.len();
+                fused_results.retain(|r| r.score >= min_score);
+                // further candidates would score even lower
+                exhausted |= fused_results.len() < fused_count;
+            }
+            if options.filters.is_empty() {
+                return Ok(fused_results);
+            }
+
+            fused_results = self.filter_results(fused_results, &options.filters).await?;
+            if fused_results.len() >= pool_size as usize
+                || exhausted
+                || candidate_count >= MAX_FILTERED_CANDIDATES
+            {
+                fused_results.truncate(pool_size as usize);
+                return Ok(fused_results);
+            }
+            candidate_count = candidate_count
+                .saturating_mul(CANDIDATES_PER_RESULT)
+                .min(MAX_FILTERED_CANDIDATES);
+        }
+    }
+
+    /// Keeps the results whose columns satisfy all the `filters`. See `filter_matches`.
+    async fn filter_results(
+        &self,
+        results: Vec<FusedResult>,
+        filters: &BTreeMap<String, serde_json::Value>,
+    ) -> anyhow::Result<Vec<FusedResult>> {
+        let keys: Vec<u64> = results.iter().map(|r| r.key).collect();
+        let columns: Vec<String> = filters.keys().cloned().collect();
+        let rows = self.get_rows(&keys, &columns).await?;
+
+        Ok(results
+            .into_iter()
+            .filter(|r| {
+                rows.get(&r.key).is_some_and(|row| {
+                    filters.iter().all(|(column, filter)| {
+                        row.get(column)
+                            .is_some_and(|value| filter_matches(value, filter))
+                    })
+                })
+            })
+            .collect())
+    }
+
+    /// Keeps the first of the results that have the same value in `column`.
+    /// Results with NULL values are all kept.
+    async fn collapse_results(
+        &self,
+        results: Vec<FusedResult>,
+        column: &str,
+    ) -> anyhow::Result<Vec<FusedResult>> {
+        let keys: Vec<u64> = results.iter().map(|r| r.key).collect();
+        let rows = self.get_rows(&keys, &[column.to_string()]).await?;
+        let mut seen_values = HashSet::new();
+
+        Ok(results
+            .into_iter()
+            .filter(|r| match rows.get(&r.key).and_then(|row| row.get(column)) {
+                None | Some(serde_json::Value::Null) => true,
+                Some(value) => seen_values.insert(value.to_string()),
+            })
+            .collect())
+    }
+
+    /// Vector stored for `key` in the index of `column_name`.
+    async fn get_vector(&self, column_name: &str, key: u64) -> anyhow::Result<Option<Vec<f32>>> {
+        let vector_index = self
+            .vector_index
+            .read()
+            .await
+            .get(column_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Index not found for {}", column_name))?;
+        let vector = vector_index.read().await.get_vector(key)?;
+        Ok(vector)
+    }
+
+    /// Searches the index of `column_name` for the query embeddings.
+    /// Each key appears once with its best score, since rows may have several vectors
+    /// when list elements are embedded separately. More vectors are fetched in that case
+    /// until there are `limit` distinct keys or the index is exhausted.
+    async fn search_index(
+        &self,
+        column_name: &str,
+        embeddings: &Embeddings,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SimilarityResult>> {
+        let vector_index = self
+            .vector_index
+            .read()
+            .await
+            .get(column_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Index not found for {}", column_name))?;
+
+        let vector_index = vector_index.read().await;
+        let limit = limit as usize;
+        let mut count = limit;
+        loop {
+            let similarity_results = match embeddings {
+                Embeddings::F16(emb) => {
+                    let (_, vector_dim) = emb.dim();
+
+                    vector_index.search::<UsearchF16>(
+                        emb.as_ptr() as *const UsearchF16,
+                        vector_dim,
+                        count,
+                    )?
+                }
+                Embeddings::F32(emb) => {
+                    let (_, vector_dim) = emb.dim();
+
+                    vector_index.search::<f32>(emb.as_ptr(), vector_dim, count)?
+                }
+            };
+            let exhausted = similarity_results.len() < count;
+
+            let mut seen_keys = HashSet::new();
+            let mut results: Vec<SimilarityResult> = similarity_results
+                .into_iter()
+                .filter(|r| seen_keys.insert(r.key))
+                .collect();
+            if results.len() >= limit || exhausted {
+                results.truncate(limit);
+                return Ok(results);
+            }
+            count = count.saturating_mul(CANDIDATES_PER_RESULT as usize);
+        }
+    }
+
     async fn add_keys_to_db(&self, tx: &duckdb::Transaction<'_>) -> anyhow::Result<()> {
         //let conn = self.conn.clone();
         //let conn_guard = conn.read().await;
 
         // Check if the '_key' column exists in the table
-        let query = format!(
-            "SELECT COUNT(*) FROM information_schema.columns WHERE table_name = '{}' AND column_name = '_key';",
-            self.config.name
-        );
         let exists: bool = {
-            let mut stmt = tx.prepare(&query)?;
-            let count: i64 = stmt.query_row([], |row| row.get(0))?;
+            let mut stmt = tx.prepare(
+                "SELECT COUNT(*) FROM information_schema.columns WHERE table_name = ? AND column_name = '_key';",
+            )?;
+            let count: i64 = stmt.query_row([self.config.name.as_str()], |row| row.get(0))?;
             count > 0
         };
 
@@ -454,7 +1075,7 @@ impl Collection {
                     r"CREATE SEQUENCE keys_seq;
     ALTER TABLE {} ADD COLUMN _key UBIGINT DEFAULT NEXTVAL('keys_seq');
     ",
-                    self.config.name,
+                    self.table(),
                 )
                 .as_str(),
             )?;
@@ -463,6 +1084,85 @@ impl Collection {
         Ok(())
     }
 
+    /// SQL expression that turns the values of `column` into texts to embed.
+    /// `column` is either a column of the table or a virtual field defined in the config.
+    /// Non-string scalars are cast to text, and list columns are either joined with newlines
+    /// or, with `ListMode::Each`, returned as a `VARCHAR[]` to be unnested (the second value is `true` then).
+    async fn text_expression(
+        &self,
+        column: &str,
+        list_mode: ListMode,
+    ) -> anyhow::Result<(String, bool)> {
+        let schema = self.schema().await?;
+        if let Some(field) = self
+            .config
+            .virtual_fields
+            .iter()
+            .find(|field| field.name == column)
+        {
+            if schema.iter().any(|c| c.name == column) {
+                return Err(anyhow::anyhow!(
+                    "Virtual field '{}' has the same name as a column of collection '{}'",
+                    column,
+                    self.config.name
+                ));
+            }
+            return Ok((virtual_field_expression(field, &schema)?, false));
+        }
+
+        let column_info = schema.iter().find(|c| c.name == column).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Column '{}' does not exist in collection '{}'",
+                column,
+                self.config.name
+            )
+        })?;
+        column_text_expression(column_info, list_mode)
+    }
+
+    /// Subquery over the texts to embed from `column` with the columns `value`, `_key`
+    /// and `idx`, i.e., the position of the value within its row for list columns.
+    /// Rows with NULL values are not filtered out here.
+    async fn texts_query(&self, column: &str) -> anyhow::Result<String> {
+        let (expression, unnest) = self.text_expression(column, self.config.list_mode).await?;
+        Ok(if unnest {
+            format!(
+                "SELECT UNNEST({}) AS value, _key, generate_subscripts({}, 1) AS idx FROM {}",
+                expression,
+                expression,
+                self.table()
+            )
+        } else {
+            format!(
+                "SELECT {} AS value, _key, 0 AS idx FROM {}",
+                expression,
+                self.table()
+            )
+        })
+    }
+
+    /// Counts the texts to embed from `column` and the rows skipped because they have no value in it.
+    pub async fn count_texts(&self, column: &str) -> anyhow::Result<(u64, u64)> {
+        let texts_query = self.texts_query(column).await?;
+        let conn_guard = self.conn.lock().await;
+        let (text_count, row_with_text_count): (i64, i64) = conn_guard.query_row(
+            format!(
+                "SELECT COUNT(*), COUNT(DISTINCT _key) FROM ({}) WHERE value IS NOT NULL;",
+                texts_query
+            )
+            .as_str(),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let row_count: i64 = conn_guard.query_row(
+            format!("SELECT COUNT(*) FROM {};", self.table()).as_str(),
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok((text_count as u64, (row_count - row_with_text_count) as u64))
+    }
+
     pub async fn get_column_and_keys(
         &self,
         column_name: &str,
@@ -470,47 +1170,894 @@ impl Collection {
         offset: u64,
     ) -> anyhow::Result<(Vec<String>, Vec<u64>)> {
         assert!(limit >= 1);
+        let texts_query = self.texts_query(column_name).await?;
         let conn = self.conn.clone();
-        let conn_guard = conn.read().await;
+        let conn_guard = conn.lock().await;
 
-        // Query the specified column and `_key` together
+        // Query the texts and `_key` together, skipping NULLs
         let mut stmt = conn_guard.prepare(
             format!(
-                "SELECT {}, _key FROM {} LIMIT {} OFFSET {};",
-                column_name, &self.config.name, limit, offset
+                "SELECT value, _key FROM ({}) WHERE value IS NOT NULL ORDER BY _key, idx LIMIT ? OFFSET ?;",
+                texts_query
             )
             .as_str(),
         )?;
+        let rows = stmt
+            .query_map(params![limit, offset], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(rows.into_iter().unzip())
+    }
+}
 
-        let result: Vec<RecordBatch> = stmt.query_arrow([])?.collect();
-        assert_eq!(result.len(), 1);
-        let batch = &result[0];
+/// A hit merged from the results of one or more columns.
+struct FusedResult {
+    key: u64,
+    score: f32,
+    /// columns the key was found in, the one contributing the most to the score first
+    matched_columns: Vec<String>,
+}
 
-        // Extract the specified column values
-        let col_array = batch
-            .column_by_name(column_name)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<StringArray>()
-            .unwrap();
-        let col_values: Vec<String> = col_array
+/// Merges the results of several columns by key.
+/// Results of each column must be sorted by score in descending order.
+fn fuse_results(
+    results_by_column: &[(&SearchColumn, Vec<SimilarityResult>)],
+    fusion: FusionMethod,
+    limit: u32,
+) -> Vec<FusedResult> {
+    // contributions of each column to the score of a key, in the order keys are first seen
+    let mut contributions: Vec<(u64, Vec<(f32, &str)>)> = Vec::new();
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    for (column, results) in results_by_column {
+        for (rank, result) in results.iter().enumerate() {
+            let contribution = match fusion {
+                FusionMethod::Max | FusionMethod::Sum => column.weight * result.score,
+                FusionMethod::Rrf => column.weight / (RRF_K + rank as f32 + 1.0),
+            };
+            let position = *positions.entry(result.key).or_insert_with(|| {
+                contributions.push((result.key, vec![]));
+                contributions.len() - 1
+            });
+            contributions[position]
+                .1
+                .push((contribution, column.column_name.as_str()));
+        }
+    }
+
+    let mut fused_results: Vec<FusedResult> = contributions
+        .into_iter()
+        .map(|(key, mut column_contributions)| {
+            column_contributions.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let score = match fusion {
+                FusionMethod::Max => column_contributions[0].0,
+                FusionMethod::Sum | FusionMethod::Rrf => {
+                    column_contributions.iter().map(|(c, _)| c).sum()
+                }
+            };
+            FusedResult {
+                key,
+                score,
+                matched_columns: column_contributions
+                    .into_iter()
+                    .map(|(_, column)| column.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
+    fused_results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused_results.truncate(limit as usize);
+
+    fused_results
+}
+
+/// Fused scores divided by the largest absolute one, so that they are on the scale of
+/// cosine similarities whatever the fusion method, e.g. the much smaller RRF scores.
+fn normalized_scores(fused_results: &[FusedResult]) -> Vec<f32> {
+    let max_score = fused_results
+        .iter()
+        .map(|r| r.score.abs())
+        .fold(0.0, f32::max);
+    fused_results
+        .iter()
+        .map(|r| {
+            if max_score > 0.0 {
+                r.score / max_score
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+/// Indices of up to `limit` candidates in maximal marginal relevance order.
+/// Each step picks the candidate maximizing
+/// `lambda * relevance[candidate] - (1 - lambda) * max sim(candidate, selected)`.
+/// Candidates without a vector are only ranked after the others, in their original order.
+fn mmr_order(
+    relevance: &[f32],
+    candidate_vectors: &[Option<Vec<f32>>],
+    lambda: f32,
+    limit: usize,
+) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..candidate_vectors.len())
+        .filter(|&i| candidate_vectors[i].is_some())
+        .collect();
+    let mut selected: Vec<usize> = Vec::with_capacity(limit);
+
+    while selected.len() < limit && !remaining.is_empty() {
+        let (position, _) = remaining
             .iter()
-            .map(|s| s.unwrap().to_string())
-            .collect::<Vec<String>>();
+            .enumerate()
+            .map(|(position, &i)| {
+                let vector = candidate_vectors[i].as_ref().unwrap();
+                let redundancy = selected
+                    .iter()
+                    .map(|&j| cosine_similarity(vector, candidate_vectors[j].as_ref().unwrap()))
+                    .fold(f32::NEG_INFINITY, f32::max);
+                let redundancy = if selected.is_empty() { 0.0 } else { redundancy };
+                (
+                    position,
+                    lambda * relevance[i] - (1.0 - lambda) * redundancy,
+                )
+            })
+            // the first of equal candidates wins to keep the original order on ties
+            .fold((0, f32::NEG_INFINITY), |best, current| {
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            });
+        selected.push(remaining.remove(position));
+    }
 
-        // Extract `_key` values
-        let key_array = batch
-            .column_by_name("_key")
-            .unwrap()
-            .as_any()
-            .downcast_ref::<PrimitiveArray<UInt64Type>>()
-            .unwrap();
-        let keys: Vec<u64> = key_array.iter().map(|key| key.unwrap_or(0)).collect();
+    selected.extend(
+        (0..candidate_vectors.len())
+            .filter(|&i| candidate_vectors[i].is_none())
+            .take(limit.saturating_sub(selected.len())),
+    );
+
+    selected
+}
+
+/// SQL expression that turns the values of a column into texts. See `Collection::text_expression`.
+fn column_text_expression(
+    column_info: &ColumnInfo,
+    list_mode: ListMode,
+) -> anyhow::Result<(String, bool)> {
+    let data_type = column_info.data_type.to_uppercase();
+    let is_unsupported = |data_type: &str| {
+        ["STRUCT", "MAP", "UNION", "BLOB", "BIT"]
+            .iter()
+            .any(|prefix| data_type.starts_with(prefix))
+    };
+    let unsupported_error = || {
+        anyhow::anyhow!(
+            "Column '{}' has type {} that cannot be embedded. Only text, scalar and list of scalar columns are supported",
+            column_info.name,
+            column_info.data_type
+        )
+    };
 
-        Ok((col_values, keys))
+    let quoted = quote_identifier(column_info.name.as_str());
+    if data_type.ends_with(']') {
+        let element_type = &data_type[..data_type.rfind('[').unwrap()];
+        if element_type.ends_with(']') || is_unsupported(element_type) {
+            return Err(unsupported_error());
+        }
+
+        // NULL and empty elements are left out
+        let list = format!("list_filter(CAST({} AS VARCHAR[]), x -> x <> '')", quoted);
+        return Ok(match list_mode {
+            ListMode::Join => (
+                format!("NULLIF(array_to_string({}, chr(10)), '')", list),
+                false,
+            ),
+            ListMode::Each => (list, true),
+        });
+    }
+
+    if is_unsupported(data_type.as_str()) {
+        return Err(unsupported_error());
+    }
+
+    // empty texts are skipped like NULLs
+    if data_type == "VARCHAR" {
+        Ok((format!("NULLIF({}, '')", quoted), false))
+    } else {
+        Ok((format!("NULLIF(CAST({} AS VARCHAR), '')", quoted), false))
     }
 }
 
+/// SQL expression that computes the texts of a virtual field from the columns in `schema`.
+fn virtual_field_expression(field: &VirtualField, schema: &[ColumnInfo]) -> anyhow::Result<String> {
+    field.validate()?;
+    if let Some(sql) = field.sql.as_ref() {
+        // unquoted names are case-insensitive in DuckDB
+        for column in parse_sql_expression(sql)? {
+            if !schema
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(column.as_str()))
+            {
+                return Err(anyhow::anyhow!(
+                    "SQL expression of virtual field '{}' refers to unknown column '{}'",
+                    field.name,
+                    column
+                ));
+            }
+        }
+        return Ok(format!("CAST(({}) AS VARCHAR)", sql));
+    }
+
+    let parts = parse_template(field.template.as_ref().unwrap())?
+        .into_iter()
+        .map(|part| match part {
+            TemplatePart::Text(text) => Ok(quote_literal(text.as_str())),
+            TemplatePart::Column(column) => {
+                let column_info = schema.iter().find(|c| c.name == column).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Template of virtual field '{}' refers to unknown column '{}'",
+                        field.name,
+                        column
+                    )
+                })?;
+                let (expression, _) = column_text_expression(column_info, ListMode::Join)?;
+                Ok(format!("COALESCE({}, '')", expression))
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(format!("NULLIF(concat({}), '')", parts.join(", ")))
+}
+
 // Needed because Rust does not understand Collection::conn is managed for thread safety.
 unsafe impl Send for Collection {}
 unsafe impl Sync for Collection {}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fuse_results, mmr_order, normalized_scores, virtual_field_expression, Collection,
+        FusedResult,
+    };
+    use crate::collection::collection_utils::{
+        collection_dir, quote_identifier, quote_literal, CollectionConfig, ExportContent,
+        ExportFormat, FusionMethod, ListMode, SearchColumn, SearchOptions, VirtualField,
+    };
+    use crate::collection::vector_index::{SimilarityResult, VectorIndex};
+    use crate::model::model_utils::Embeddings;
+    use ndarray::Array2;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use usearch::{IndexOptions, MetricKind, ScalarKind};
+
+    fn read_jsonl(path: &std::path::Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_open_version_1() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_open_version_1");
+        let collection = Collection::new(config, true).await.unwrap();
+        drop(collection);
+
+        // configs written before version 2 have neither its fields nor a version
+        let config_path = collection_dir("test_open_version_1").join("config.json");
+        let mut raw: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        let fields = raw.as_object_mut().unwrap();
+        for field in ["serialization_version", "list_mode", "virtual_fields"] {
+            fields.remove(field);
+        }
+        fs::write(&config_path, raw.to_string()).unwrap();
+
+        let collection = Collection::from(String::from("test_open_version_1"))
+            .await
+            .unwrap();
+        let config = collection.config();
+        assert_eq!(config.serialization_version, 1);
+        assert_eq!(config.list_mode, ListMode::Join);
+        assert!(config.virtual_fields.is_empty());
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_open_version_1")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_export");
+        let tmp_dir = std::env::temp_dir();
+        let jsonl_path = tmp_dir.join("test_export_input.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"text\": \"first\"}\n{\"text\": \"second\"}\n",
+        )
+        .unwrap();
+        let collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // index a vector only for the first row
+        let options = IndexOptions {
+            dimensions: 3,
+            metric: MetricKind::Cos,
+            quantization: ScalarKind::F32,
+            connectivity: 0,
+            expansion_add: 0,
+            expansion_search: 0,
+            multi: true,
+        };
+        let mut index =
+            VectorIndex::new(collection_dir("test_export").join("index/text"), true).unwrap();
+        index.with_options(&options, 10).unwrap();
+        let vector: Vec<f32> = vec![0.5, 0.25, 1.0];
+        index.add(&vec![1], vector.as_ptr(), 3).await.unwrap();
+        collection
+            .vector_index
+            .write()
+            .await
+            .insert(String::from("text"), Arc::new(RwLock::new(index)));
+
+        let output = tmp_dir.join("test_export_output.jsonl");
+        let output_str = output.to_str().unwrap();
+        let count = collection
+            .export(output_str, ExportFormat::Jsonl, ExportContent::All)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        let rows = read_jsonl(&output);
+        assert_eq!(rows[0]["text"], "first");
+        assert_eq!(
+            rows[0]["text_embedding"],
+            serde_json::json!([0.5, 0.25, 1.0])
+        );
+        assert_eq!(rows[1]["text"], "second");
+        assert!(rows[1]["text_embedding"].is_null());
+
+        collection
+            .export(output_str, ExportFormat::Jsonl, ExportContent::MetadataOnly)
+            .await
+            .unwrap();
+        let rows = read_jsonl(&output);
+        assert!(rows[0].get("text_embedding").is_none());
+        assert_eq!(rows[0]["text"], "first");
+
+        collection
+            .export(output_str, ExportFormat::Jsonl, ExportContent::VectorsOnly)
+            .await
+            .unwrap();
+        let rows = read_jsonl(&output);
+        assert!(rows[0].get("text").is_none());
+        assert_eq!(rows[0]["_key"], 1);
+
+        // non-finite components are kept as they are
+        let vector: Vec<f32> = vec![f32::NAN, f32::INFINITY, 1.0];
+        collection
+            .vector_index
+            .read()
+            .await
+            .get("text")
+            .unwrap()
+            .read()
+            .await
+            .add(&vec![2], vector.as_ptr(), 3)
+            .await
+            .unwrap();
+        {
+            let conn = collection.conn.lock().await;
+            collection
+                .write_vectors_to_temp_table(&conn, "text", &[1, 2])
+                .await
+                .unwrap();
+            let (is_nan, is_inf): (bool, bool) = conn
+                .query_row(
+                    "SELECT isnan(text_embedding[1]), isinf(text_embedding[2]) FROM _export_text WHERE _key = 2;",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert!(is_nan && is_inf);
+        }
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_export")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sql_injection() {
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_literal("it's"), "'it''s'");
+
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_injection; DROP TABLE x");
+        assert!(Collection::new(config, true).await.is_err());
+
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_injection");
+        let jsonl_path = std::env::temp_dir().join("test_injection's.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"text\": \"first\", \"odd \\\"column\": 1}\n{\"text\": \"second\", \"odd \\\"column\": 2}\n",
+        )
+        .unwrap();
+        let collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let malicious = "text FROM test_injection; DROP TABLE test_injection; --";
+        assert!(collection
+            .get_single_column(malicious, 1, 0, vec![])
+            .await
+            .is_err());
+        assert!(collection
+            .get_rows(&[1], &[malicious.to_string()])
+            .await
+            .is_err());
+        assert_eq!(collection.stats().await.unwrap().row_count, 2);
+
+        let texts = collection
+            .get_single_column("text", 10, 0, vec![2])
+            .await
+            .unwrap();
+        assert_eq!(texts, vec!["second"]);
+        let rows = collection
+            .get_rows(&[1, 2], &[String::from("odd \"column")])
+            .await
+            .unwrap();
+        assert_eq!(rows[&1]["odd \"column"], 1);
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_injection")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_column_types() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_column_types");
+        let jsonl_path = std::env::temp_dir().join("test_column_types.jsonl");
+        let records = [
+            r#"{"text": "first", "number": 1, "tags": ["a", "b"], "nested": {"x": 1}}"#,
+            r#"{"text": null, "number": 2.5, "tags": null, "nested": {"x": 2}}"#,
+            r#"{"text": "third", "number": null, "tags": ["c", null], "nested": {"x": 3}}"#,
+            r#"{"text": "", "number": 4, "tags": ["", "d"], "nested": {"x": 4}}"#,
+        ];
+        fs::write(&jsonl_path, records.join("\n")).unwrap();
+        let mut collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // NULLs and empty texts are skipped and reported
+        assert_eq!(collection.count_texts("text").await.unwrap(), (2, 2));
+        let (texts, keys) = collection.get_column_and_keys("text", 10, 0).await.unwrap();
+        assert_eq!(texts, vec!["first", "third"]);
+        assert_eq!(keys, vec![1, 3]);
+
+        // non-string scalars are cast to text
+        let (texts, _) = collection
+            .get_column_and_keys("number", 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(texts, vec!["1.0", "2.5", "4.0"]);
+
+        // list elements are joined by default
+        let (texts, keys) = collection.get_column_and_keys("tags", 10, 0).await.unwrap();
+        assert_eq!(texts, vec!["a\nb", "c", "d"]);
+        assert_eq!(keys, vec![1, 3, 4]);
+
+        // or embedded separately
+        collection.config.list_mode = ListMode::Each;
+        assert_eq!(collection.count_texts("tags").await.unwrap(), (4, 1));
+        let (texts, keys) = collection.get_column_and_keys("tags", 2, 1).await.unwrap();
+        assert_eq!(texts, vec!["b", "c"]);
+        assert_eq!(keys, vec![1, 3]);
+
+        let error = collection.count_texts("nested").await.unwrap_err();
+        assert!(error.to_string().contains("cannot be embedded"));
+
+        // contents are returned in the order of the keys
+        let contents = collection
+            .get_single_column("text", 3, 0, vec![3, 1])
+            .await
+            .unwrap();
+        assert_eq!(contents, vec!["third", "first"]);
+
+        // missing keys are left out of the rows
+        let columns = vec![
+            String::from("text"),
+            String::from("number"),
+            String::from("tags"),
+            String::from("nested"),
+        ];
+        let rows = collection.get_rows(&[3, 42, 1], &columns).await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            serde_json::Value::Object(rows[&1].clone()),
+            serde_json::json!({"text": "first", "number": 1.0, "tags": ["a", "b"], "nested": {"x": 1}})
+        );
+        assert_eq!(
+            serde_json::Value::Object(rows[&3].clone()),
+            serde_json::json!({"text": "third", "number": null, "tags": ["c", null], "nested": {"x": 3}})
+        );
+        assert!(collection
+            .get_rows(&[42], &columns)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // unknown columns are an error rather than an empty field
+        let unknown = vec![String::from("text"), String::from("unknown")];
+        assert!(collection.get_rows(&[1], &unknown).await.is_err());
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_column_types")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+
+    #[test]
+    fn test_fuse_results() {
+        let results = |pairs: &[(u64, f32)]| {
+            pairs
+                .iter()
+                .map(|&(key, score)| SimilarityResult { key, score })
+                .collect::<Vec<_>>()
+        };
+        let title = SearchColumn {
+            column_name: String::from("title"),
+            weight: 2.0,
+        };
+        let body = SearchColumn::new("body");
+        let results_by_column = vec![
+            (&title, results(&[(1, 0.5), (2, 0.4)])),
+            (&body, results(&[(3, 0.9), (1, 0.6)])),
+        ];
+
+        let fused = fuse_results(&results_by_column, FusionMethod::Max, 10);
+        let keys: Vec<u64> = fused.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![1, 3, 2]);
+        assert_eq!(fused[0].score, 1.0);
+        assert_eq!(fused[0].matched_columns, vec!["title", "body"]);
+        assert_eq!(fused[1].matched_columns, vec!["body"]);
+
+        let fused = fuse_results(&results_by_column, FusionMethod::Sum, 2);
+        assert_eq!(fused.len(), 2);
+        assert_eq!(fused[0].key, 1);
+        assert!((fused[0].score - 1.6).abs() < 1e-6);
+
+        // ranks matter instead of scores
+        let fused = fuse_results(&results_by_column, FusionMethod::Rrf, 10);
+        let keys: Vec<u64> = fused.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![1, 2, 3]);
+        assert!((fused[0].score - (2.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mmr_order() {
+        let relevance = vec![0.99, 1.0, 0.75];
+        let vectors = vec![
+            Some(vec![1.0, 0.0]),
+            Some(vec![1.0, 0.05]),
+            Some(vec![0.6, 0.8]),
+        ];
+
+        // only relevance
+        assert_eq!(mmr_order(&relevance, &vectors, 1.0, 3), vec![1, 0, 2]);
+        // the near duplicate of the first hit is pushed down
+        assert_eq!(mmr_order(&relevance, &vectors, 0.5, 3), vec![1, 2, 0]);
+        assert_eq!(mmr_order(&relevance, &vectors, 0.5, 2), vec![1, 2]);
+
+        let vectors = vec![Some(vec![1.0, 0.0]), None, Some(vec![0.6, 0.8])];
+        assert_eq!(mmr_order(&relevance, &vectors, 1.0, 3), vec![0, 2, 1]);
+
+        // RRF scores are rescaled to compare with similarities
+        let fused = vec![
+            FusedResult {
+                key: 1,
+                score: 2.0 / 61.0,
+                matched_columns: vec![],
+            },
+            FusedResult {
+                key: 2,
+                score: 1.0 / 61.0,
+                matched_columns: vec![],
+            },
+        ];
+        assert_eq!(normalized_scores(&fused), vec![1.0, 0.5]);
+    }
+
+    #[tokio::test]
+    async fn test_collapse_results() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_collapse_results");
+        let jsonl_path = std::env::temp_dir().join("test_collapse_results.jsonl");
+        let records = [
+            r#"{"text": "a1", "document_id": "a"}"#,
+            r#"{"text": "b1", "document_id": "b"}"#,
+            r#"{"text": "a2", "document_id": "a"}"#,
+            r#"{"text": "x", "document_id": null}"#,
+            r#"{"text": "y", "document_id": null}"#,
+        ];
+        fs::write(&jsonl_path, records.join("\n")).unwrap();
+        let collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let results = [3, 1, 2, 4, 5]
+            .iter()
+            .map(|&key| FusedResult {
+                key,
+                score: 1.0,
+                matched_columns: vec![String::from("text")],
+            })
+            .collect();
+        let collapsed = collection
+            .collapse_results(results, "document_id")
+            .await
+            .unwrap();
+        let keys: Vec<u64> = collapsed.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![3, 2, 4, 5]);
+
+        let filters =
+            BTreeMap::from([(String::from("document_id"), serde_json::json!(["a", "c"]))]);
+        let filtered = collection
+            .filter_results(collapsed, &filters)
+            .await
+            .unwrap();
+        let keys: Vec<u64> = filtered.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![3]);
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_collapse_results")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_index_distinct_keys() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_search_index_distinct_keys");
+        config.index_columns = vec![String::from("tags")];
+        let collection = Collection::new(config, true).await.unwrap();
+        assert!(collection.check_indexes().await.is_err());
+
+        let options = IndexOptions {
+            dimensions: 2,
+            metric: MetricKind::Cos,
+            quantization: ScalarKind::F32,
+            connectivity: 0,
+            expansion_add: 0,
+            expansion_search: 0,
+            multi: true,
+        };
+        let mut index = VectorIndex::new(
+            collection_dir("test_search_index_distinct_keys").join("index/tags"),
+            true,
+        )
+        .unwrap();
+        index.with_options(&options, 10).unwrap();
+        // the elements of row 1 are all closer to the query than the only one of row 2
+        let vectors: Vec<f32> = vec![1.0, 0.0, 1.0, 0.01, 1.0, 0.02, 1.0, 0.03, 0.0, 1.0];
+        index
+            .add(&vec![1, 1, 1, 1, 2], vectors.as_ptr(), 2)
+            .await
+            .unwrap();
+        collection
+            .vector_index
+            .write()
+            .await
+            .insert(String::from("tags"), Arc::new(RwLock::new(index)));
+        collection.check_indexes().await.unwrap();
+
+        let query = Embeddings::F32(Arc::new(
+            Array2::from_shape_vec((1, 2), vec![1.0, 0.0]).unwrap(),
+        ));
+        let results = collection.search_index("tags", &query, 2).await.unwrap();
+        let keys: Vec<u64> = results.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![1, 2]);
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_search_index_distinct_keys")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_candidates_filters() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_search_candidates_filters");
+        let jsonl_path = std::env::temp_dir().join("test_search_candidates_filters.jsonl");
+        // only the row least similar to the query matches the filter
+        let records: Vec<String> = (1..=20)
+            .map(|i| {
+                let lang = if i == 20 { "fr" } else { "en" };
+                format!(r#"{{"text": "t{}", "lang": "{}"}}"#, i, lang)
+            })
+            .collect();
+        fs::write(&jsonl_path, records.join("\n")).unwrap();
+        let collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let options = IndexOptions {
+            dimensions: 2,
+            metric: MetricKind::Cos,
+            quantization: ScalarKind::F32,
+            connectivity: 0,
+            expansion_add: 0,
+            expansion_search: 0,
+            multi: false,
+        };
+        let mut index = VectorIndex::new(
+            collection_dir("test_search_candidates_filters").join("index/text"),
+            true,
+        )
+        .unwrap();
+        index.with_options(&options, 20).unwrap();
+        let keys: Vec<u64> = (1..=20).collect();
+        let vectors: Vec<f32> = keys
+            .iter()
+            .flat_map(|&key| [1.0, key as f32 * 0.1])
+            .collect();
+        index.add(&keys, vectors.as_ptr(), 2).await.unwrap();
+        collection
+            .vector_index
+            .write()
+            .await
+            .insert(String::from("text"), Arc::new(RwLock::new(index)));
+
+        let query = Embeddings::F32(Arc::new(
+            Array2::from_shape_vec((1, 2), vec![1.0, 0.0]).unwrap(),
+        ));
+        let mut options = SearchOptions::default();
+        options.columns = vec![SearchColumn::new("text")];
+        options.limit = 1;
+        options.filters = BTreeMap::from([(String::from("lang"), serde_json::json!("fr"))]);
+        let results = collection
+            .search_candidates(&query, &options)
+            .await
+            .unwrap();
+        let keys: Vec<u64> = results.iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec![20]);
+
+        options.filters = BTreeMap::from([(String::from("lang"), serde_json::json!("de"))]);
+        let results = collection
+            .search_candidates(&query, &options)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_search_candidates_filters")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_virtual_fields() {
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_virtual_fields");
+        config.virtual_fields = vec![
+            VirtualField {
+                name: String::from("title_body"),
+                template: Some(String::from("{title}\n{body} {{{year}}}")),
+                sql: None,
+            },
+            VirtualField {
+                name: String::from("summary"),
+                template: None,
+                sql: Some(String::from("upper(title) || ': ' || body")),
+            },
+            VirtualField {
+                name: String::from("missing"),
+                template: Some(String::from("{title} {unknown}")),
+                sql: None,
+            },
+            VirtualField {
+                name: String::from("sql_missing"),
+                template: None,
+                sql: Some(String::from("title || \"unknown\"")),
+            },
+        ];
+        let jsonl_path = std::env::temp_dir().join("test_virtual_fields.jsonl");
+        let records = [
+            r#"{"title": "First", "body": "one", "year": 2020}"#,
+            r#"{"title": "Second", "body": null, "year": null}"#,
+        ];
+        fs::write(&jsonl_path, records.join("\n")).unwrap();
+        let collection = Collection::new(config, true).await.unwrap();
+        collection
+            .import_jsonl(jsonl_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // NULL columns are empty in templates
+        let (texts, keys) = collection
+            .get_column_and_keys("title_body", 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(texts, vec!["First\none {2020}", "Second\n {}"]);
+        assert_eq!(keys, vec![1, 2]);
+
+        // but make SQL expressions NULL, and such rows are skipped
+        assert_eq!(collection.count_texts("summary").await.unwrap(), (1, 1));
+        let contents = collection
+            .get_single_column("summary", 2, 0, vec![1])
+            .await
+            .unwrap();
+        assert_eq!(contents, vec!["FIRST: one"]);
+
+        let rows = collection
+            .get_rows(&[2], &[String::from("title_body"), String::from("title")])
+            .await
+            .unwrap();
+        assert_eq!(rows[&2]["title_body"], "Second\n {}");
+        assert_eq!(rows[&2]["title"], "Second");
+
+        let error = collection.count_texts("missing").await.unwrap_err();
+        assert!(error.to_string().contains("unknown column 'unknown'"));
+
+        let error = collection.count_texts("sql_missing").await.unwrap_err();
+        assert!(error.to_string().contains("unknown column 'unknown'"));
+
+        // SQL expressions are limited to columns, literals, operators and string functions
+        let sql_field = |sql: &str| VirtualField {
+            name: String::from("sql"),
+            template: None,
+            sql: Some(String::from(sql)),
+        };
+        for sql in [
+            "title || '; ' || body",
+            "concat_ws(' - ', upper(\"title\"), CAST(year AS VARCHAR))",
+            "CASE WHEN year > 2000 THEN 'new' ELSE 'old' END",
+        ] {
+            assert!(sql_field(sql).validate().is_ok(), "{}", sql);
+        }
+        for sql in [
+            "title; DROP TABLE test_virtual_fields",
+            "read_text('/etc/passwd')",
+            "getenv('HOME')",
+            "(SELECT content FROM read_text('/etc/passwd'))",
+            "main.test_virtual_fields.title",
+            "upper(title",
+        ] {
+            assert!(sql_field(sql).validate().is_err(), "{}", sql);
+        }
+        // words that are not functions or keywords must be columns, so subqueries are refused too
+        let schema = collection.schema().await.unwrap();
+        let subquery = sql_field("(SELECT title FROM 'data.csv')");
+        assert!(virtual_field_expression(&subquery, &schema).is_err());
+
+        drop(collection);
+        fs::remove_dir_all(collection_dir("test_virtual_fields")).unwrap();
+        fs::remove_file(jsonl_path).unwrap();
+    }
+}
@@ -1,5 +1,16 @@
+use super::migrations::CURRENT_SERIALIZATION_VERSION;
+use super::vector_index::VectorIndexStats;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use duckdb::types::Value;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use utoipa::ToSchema;
 
 const DEFAULT_HOME_DIR: &str = ".letsearch";
 
@@ -9,6 +20,216 @@ pub fn home_dir() -> PathBuf {
         .into()
 }
 
+/// Directory of the collection named `name` under `LETSEARCH_HOME/collections`.
+pub fn collection_dir(name: &str) -> PathBuf {
+    home_dir().join("collections").join(name)
+}
+
+/// Collection names are used as directory and table names,
+/// so they are limited to ASCII letters, digits and underscores.
+pub fn validate_collection_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid collection name '{}'. Use only ASCII letters, digits and underscores, not starting with a digit",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Quotes `name` as a DuckDB identifier such as a table or column name.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quotes `value` as a DuckDB string literal such as a file path.
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Copies the directory at `from` to `to` recursively.
+pub fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks a collection as being served by this process until it's dropped,
+/// so that other letsearch processes do not modify it underneath the server.
+/// Each server process has its own `server.<pid>.lock` file in the collection directory.
+pub struct ServerLock {
+    path: PathBuf,
+}
+
+impl ServerLock {
+    pub fn acquire(name: &str) -> anyhow::Result<Self> {
+        let path = collection_dir(name).join(format!("server.{}.lock", std::process::id()));
+        fs::write(&path, std::process::id().to_string())?;
+        Ok(ServerLock { path })
+    }
+
+    /// Writes the lock file again if it's gone, e.g. after the collection was re-created on disk.
+    pub fn refresh(&self) -> anyhow::Result<()> {
+        if !self.path.exists() {
+            fs::write(&self.path, std::process::id().to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Process IDs of the server lock files in a collection directory.
+fn server_lock_pids(dir: &Path) -> Vec<(PathBuf, u32)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let pid = file_name
+                .strip_prefix("server.")?
+                .strip_suffix(".lock")?
+                .parse::<u32>()
+                .ok()?;
+            Some((entry.path(), pid))
+        })
+        .collect()
+}
+
+/// Removes all the server lock files in a collection directory, e.g., after copying it.
+pub fn remove_server_locks(dir: &Path) -> anyhow::Result<()> {
+    for (path, _) in server_lock_pids(dir) {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+impl Drop for ServerLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_is_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+// We cannot tell whether the process is still alive elsewhere, so assume it is.
+#[cfg(not(target_os = "linux"))]
+fn process_is_alive(_pid: u32) -> bool {
+    true
+}
+
+/// ID of a process serving the collection, if any.
+/// Locks left behind by processes that are no longer alive are ignored.
+pub fn server_lock_holder(name: &str) -> Option<u32> {
+    server_lock_pids(&collection_dir(name))
+        .into_iter()
+        .map(|(_, pid)| pid)
+        .find(|pid| process_is_alive(*pid))
+}
+
+/// Modification time and size of each file under `dir` except server lock files,
+/// to tell when a collection is modified on disk, e.g., by re-indexing it.
+pub fn collection_files_state(dir: &Path) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    let mut state = BTreeMap::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return state,
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => state.extend(collection_files_state(&path)),
+            Ok(metadata) => {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if file_name.starts_with("server.") && file_name.ends_with(".lock") {
+                    continue;
+                }
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                state.insert(path, (modified, metadata.len()));
+            }
+            Err(_) => continue,
+        }
+    }
+
+    state
+}
+
+/// Total size in bytes of the files under `path`. Returns 0 if it does not exist.
+pub fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|m| m.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+/// Human-readable representation of a size in bytes, e.g., `12.3 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Reads the configs of all the collections on disk under `LETSEARCH_HOME/collections`.
+/// Directories without a readable `config.json` are skipped.
+pub fn list_collection_configs() -> anyhow::Result<Vec<CollectionConfig>> {
+    let collections_dir = home_dir().join("collections");
+    if !collections_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut configs = Vec::new();
+    for entry in fs::read_dir(collections_dir)? {
+        let config_path = entry?.path().join("config.json");
+        if let Ok(config_file) = File::open(config_path) {
+            if let Ok(config) = serde_json::from_reader::<_, CollectionConfig>(config_file) {
+                configs.push(config);
+            }
+        }
+    }
+    configs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(configs)
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct CollectionConfig {
     #[serde(default = "default_collection_name")]
@@ -19,12 +240,304 @@ pub struct CollectionConfig {
     pub model_name: String,
     #[serde(default = "default_model_variant")]
     pub model_variant: String,
+    /// commit sha of the model repo the collection was indexed with
+    #[serde(default)]
+    pub model_revision: Option<String>,
     #[serde(default = "default_db_path")]
     pub db_path: String,
     #[serde(default = "default_index_dir")]
     pub index_dir: String,
     #[serde(default = "default_serialization_version")]
     pub serialization_version: u32,
+    /// how list columns are embedded
+    #[serde(default)]
+    pub list_mode: ListMode,
+    /// fields computed from other columns that can be indexed and searched like columns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub virtual_fields: Vec<VirtualField>,
+}
+
+/// A field computed from the columns of a collection,
+/// defined either by a template or by a SQL expression.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct VirtualField {
+    pub name: String,
+    /// template such as `"{title}\n{body}"`. `{column}` is replaced with the value of the column,
+    /// and `{{` and `}}` are literal braces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// SQL expression over the columns of the table such as `title || ': ' || body`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sql: Option<String>,
+}
+
+/// A piece of a virtual field template.
+#[derive(Clone, PartialEq, Debug)]
+pub enum TemplatePart {
+    Text(String),
+    Column(String),
+}
+
+impl VirtualField {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow::anyhow!(
+                "Invalid virtual field name '{}'. Use only ASCII letters, digits and underscores",
+                self.name
+            ));
+        }
+
+        match (&self.template, &self.sql) {
+            (Some(template), None) => {
+                parse_template(template)?;
+            }
+            (None, Some(sql)) => {
+                parse_sql_expression(sql).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Invalid SQL expression of virtual field '{}': {}",
+                        self.name,
+                        e
+                    )
+                })?;
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Virtual field '{}' must have either a template or a SQL expression",
+                    self.name
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a template such as `"{title}\n{body}"` into literal texts and column references.
+pub fn parse_template(template: &str) -> anyhow::Result<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut column = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => column.push(c),
+                        None => {
+                            return Err(anyhow::anyhow!("Unclosed '{{' in template '{}'", template))
+                        }
+                    }
+                }
+                if column.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Empty column name in template '{}'",
+                        template
+                    ));
+                }
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(TemplatePart::Column(column));
+            }
+            '}' => {
+                return Err(anyhow::anyhow!(
+                    "Unmatched '}}' in template '{}'. Use '}}}}' for a literal brace",
+                    template
+                ))
+            }
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+
+    Ok(parts)
+}
+
+/// Scalar functions that SQL expressions of virtual fields may call.
+/// Anything that could read files, the environment or other tables is left out.
+const SQL_EXPRESSION_FUNCTIONS: &[&str] = &[
+    "abs",
+    "array_to_string",
+    "cast",
+    "coalesce",
+    "concat",
+    "concat_ws",
+    "contains",
+    "format",
+    "ifnull",
+    "left",
+    "length",
+    "lower",
+    "lpad",
+    "ltrim",
+    "nullif",
+    "printf",
+    "regexp_replace",
+    "repeat",
+    "replace",
+    "reverse",
+    "right",
+    "round",
+    "rpad",
+    "rtrim",
+    "starts_with",
+    "strftime",
+    "strip_accents",
+    "substr",
+    "substring",
+    "trim",
+    "try_cast",
+    "upper",
+];
+
+/// Keywords and type names that SQL expressions of virtual fields may use besides column names.
+const SQL_EXPRESSION_KEYWORDS: &[&str] = &[
+    "and",
+    "as",
+    "between",
+    "bigint",
+    "boolean",
+    "case",
+    "date",
+    "double",
+    "else",
+    "end",
+    "false",
+    "ilike",
+    "in",
+    "integer",
+    "is",
+    "like",
+    "not",
+    "null",
+    "or",
+    "text",
+    "then",
+    "timestamp",
+    "true",
+    "varchar",
+    "when",
+];
+
+/// Checks that `sql` is a single expression made of column references, literals, operators
+/// and the functions in `SQL_EXPRESSION_FUNCTIONS`, and returns the names of the columns it refers to.
+/// Subqueries, table functions and statement separators are refused, since the expression is
+/// embedded into queries as is and may come from a pulled or unpacked collection.
+pub fn parse_sql_expression(sql: &str) -> anyhow::Result<Vec<String>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut columns = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '\'' | '"' => {
+                // quotes are escaped by doubling them
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                            value.push(c);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => break,
+                        Some(&other) => {
+                            value.push(other);
+                            i += 1;
+                        }
+                        None => {
+                            return Err(anyhow::anyhow!("Unterminated quote {} in '{}'", c, sql))
+                        }
+                    }
+                }
+                i += 1;
+                if c == '"' {
+                    columns.push(value);
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let lowercase = word.to_lowercase();
+                let is_call = chars[i..]
+                    .iter()
+                    .find(|c| !c.is_whitespace())
+                    .is_some_and(|&c| c == '(');
+                if is_call {
+                    if !SQL_EXPRESSION_FUNCTIONS.contains(&lowercase.as_str()) {
+                        return Err(anyhow::anyhow!(
+                            "Function '{}' is not allowed in SQL expressions",
+                            word
+                        ));
+                    }
+                } else if !SQL_EXPRESSION_KEYWORDS.contains(&lowercase.as_str()) {
+                    columns.push(word);
+                }
+            }
+            c if c.is_ascii_digit() => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+            }
+            '(' => {
+                depth += 1;
+                i += 1;
+            }
+            ')' => {
+                if depth == 0 {
+                    return Err(anyhow::anyhow!("Unmatched ')' in '{}'", sql));
+                }
+                depth -= 1;
+                i += 1;
+            }
+            '|' if chars.get(i + 1) == Some(&'|') => i += 2,
+            '+' | '-' | '*' | '/' | '%' | ',' | '=' | '<' | '>' | '!' => i += 1,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "'{}' is not allowed in SQL expressions",
+                    other
+                ))
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(anyhow::anyhow!("Unmatched '(' in '{}'", sql));
+    }
+
+    Ok(columns)
+}
+
+/// How the values of a list column such as `VARCHAR[]` are embedded.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ListMode {
+    /// elements are joined with newlines and embedded as a single text
+    #[default]
+    Join,
+    /// each element is embedded separately under the key of its row
+    Each,
 }
 
 fn default_collection_name() -> String {
@@ -51,6 +564,7 @@ fn default_index_dir() -> String {
     String::from("index")
 }
 
+/// Configs written before `serialization_version` was introduced are version 1.
 fn default_serialization_version() -> u32 {
     1
 }
@@ -62,16 +576,553 @@ impl CollectionConfig {
             index_columns: default_index_columns(),
             model_name: default_model_name(),
             model_variant: default_model_variant(),
+            model_revision: None,
             db_path: default_db_path(),
             index_dir: default_index_dir(),
-            serialization_version: default_serialization_version(),
+            serialization_version: CURRENT_SERIALIZATION_VERSION,
+            list_mode: ListMode::default(),
+            virtual_fields: vec![],
+        }
+    }
+
+    /// Model path pinned to `model_revision` if it's recorded, e.g., `hf://user/repo@<sha>`.
+    pub fn pinned_model_name(&self) -> String {
+        match &self.model_revision {
+            Some(revision) if self.model_name.starts_with("hf://") => {
+                let repo = self.model_name.split('@').next().unwrap();
+                format!("{}@{}", repo, revision)
+            }
+            _ => self.model_name.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub data_type: String,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct IndexedColumnStats {
+    pub column: String,
+    #[serde(flatten)]
+    pub index: VectorIndexStats,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct CollectionStats {
+    pub name: String,
+    pub row_count: u64,
+    pub schema: Vec<ColumnInfo>,
+    pub indexes: Vec<IndexedColumnStats>,
+    pub model_name: String,
+    pub model_variant: String,
+    pub model_revision: Option<String>,
+    pub db_size: u64,
+    pub index_size: u64,
+    pub total_size: u64,
+    pub serialization_version: u32,
+}
+
+/// File format of an exported collection.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    /// a Parquet file
+    Parquet,
+    /// a JSON object per row on each line
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Infers the format from the extension of the output path.
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        if path.ends_with(".parquet") {
+            Ok(ExportFormat::Parquet)
+        } else if path.ends_with(".jsonl") || path.ends_with(".json") {
+            Ok(ExportFormat::Jsonl)
+        } else {
+            Err(anyhow::anyhow!(
+                "Cannot infer the export format from {}. Use a .parquet or .jsonl extension or specify the format",
+                path
+            ))
         }
     }
 }
 
-#[derive(Serialize)]
+/// What to include in an exported collection.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportContent {
+    /// all columns plus a `<column>_embedding` column for each indexed column
+    All,
+    /// only the columns of the DuckDB table
+    MetadataOnly,
+    /// only `_key` and the `<column>_embedding` columns
+    VectorsOnly,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct SearchResult {
     pub content: String,
     pub key: u64,
     pub score: f32,
+    /// columns the key was found in, the one contributing the most to the score first.
+    /// `content` is the value of the first one
+    pub matched_columns: Vec<String>,
+}
+
+/// How the scores of a key found in several columns are combined.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// highest weighted score
+    #[default]
+    Max,
+    /// sum of the weighted scores
+    Sum,
+    /// reciprocal rank fusion: sum of `weight / (60 + rank)`
+    Rrf,
+}
+
+impl std::str::FromStr for FusionMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(FusionMethod::Max),
+            "sum" => Ok(FusionMethod::Sum),
+            "rrf" => Ok(FusionMethod::Rrf),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported fusion method: {}. Use max, sum or rrf",
+                s
+            )),
+        }
+    }
+}
+
+/// An indexed column to search in and the weight of its scores.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, ToSchema)]
+pub struct SearchColumn {
+    pub column_name: String,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+impl SearchColumn {
+    pub fn new(column_name: &str) -> Self {
+        SearchColumn {
+            column_name: column_name.to_string(),
+            weight: default_weight(),
+        }
+    }
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Maximal marginal relevance options.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, ToSchema)]
+pub struct MmrOptions {
+    /// trade-off between relevance to the query (1.0) and diversity of the results (0.0)
+    #[serde(default = "default_mmr_lambda")]
+    pub lambda: f32,
+    /// number of candidates to re-rank. Defaults to 4 times the limit
+    #[serde(default)]
+    pub pool_size: Option<u32>,
+}
+
+impl Default for MmrOptions {
+    fn default() -> Self {
+        MmrOptions {
+            lambda: default_mmr_lambda(),
+            pool_size: None,
+        }
+    }
+}
+
+fn default_mmr_lambda() -> f32 {
+    0.5
+}
+
+/// Number of candidates fetched per result when results are re-ranked or collapsed.
+pub const CANDIDATES_PER_RESULT: u32 = 4;
+
+/// Most candidates fetched from each index to find hits matching the filters of a search.
+pub const MAX_FILTERED_CANDIDATES: u32 = 100_000;
+
+/// How deep a server lets clients page through results, as a multiple of its limit cap.
+pub const MAX_PAGES: u32 = 10;
+
+/// Options of a search over one or more indexed columns of a collection.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchOptions {
+    pub columns: Vec<SearchColumn>,
+    pub limit: u32,
+    /// how results from several columns are merged by `_key`
+    pub fusion: FusionMethod,
+    /// re-rank candidates with maximal marginal relevance to diversify the results
+    pub mmr: Option<MmrOptions>,
+    /// keep only the best hit among those with the same value in this column.
+    /// Fewer than `limit` results may be returned when many candidates share values
+    pub collapse_column: Option<String>,
+    /// drop hits scoring lower than this. Scores are on the scale of `fusion`,
+    /// i.e. cosine similarities for `max` and `sum` but much smaller values for `rrf`
+    pub min_score: Option<f32>,
+    /// number of hits to skip
+    pub offset: u32,
+    /// keep only hits whose columns are equal to these values,
+    /// or to one of the elements if a value is an array
+    pub filters: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            columns: vec![],
+            limit: 10,
+            fusion: FusionMethod::default(),
+            mmr: None,
+            collapse_column: None,
+            min_score: None,
+            offset: 0,
+            filters: BTreeMap::new(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Number of hits needed to return the page at `offset`.
+    pub fn window(&self) -> u32 {
+        self.offset.saturating_add(self.limit)
+    }
+
+    /// Number of candidates to re-rank or collapse, i.e. to fetch from each index
+    /// or, with filters, to keep after filtering.
+    pub fn candidate_count(&self) -> u32 {
+        let default_pool_size = self.window().saturating_mul(CANDIDATES_PER_RESULT);
+        match (&self.mmr, &self.collapse_column) {
+            (Some(mmr), _) => mmr
+                .pool_size
+                .unwrap_or(default_pool_size)
+                .max(self.window()),
+            (None, Some(_)) => default_pool_size,
+            (None, None) => self.window(),
+        }
+    }
+
+    /// Identifies the query and the options that affect the order of hits,
+    /// so that a cursor can't be used to page through the results of another search.
+    fn fingerprint(&self, query: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            format!(
+                "{}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}",
+                query,
+                self.columns,
+                self.fusion,
+                self.mmr,
+                self.collapse_column,
+                self.min_score,
+                self.filters
+            )
+            .as_bytes(),
+        );
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
+    /// Opaque cursor to fetch the page after the one returned for `query`,
+    /// or `None` if `returned` hits show that there are no more.
+    pub fn next_cursor(&self, query: &str, returned: usize) -> Option<String> {
+        if returned < self.limit as usize {
+            return None;
+        }
+
+        let cursor = format!("{}:{}", self.window(), self.fingerprint(query));
+        Some(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(cursor))
+    }
+
+    /// Sets `offset` from a cursor returned by `next_cursor` for the same query and options.
+    pub fn apply_cursor(&mut self, query: &str, cursor: &str) -> anyhow::Result<()> {
+        let invalid_cursor = || anyhow::anyhow!("Invalid cursor");
+        let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| invalid_cursor())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid_cursor())?;
+        let (offset, fingerprint) = decoded.split_once(':').ok_or_else(invalid_cursor)?;
+        if fingerprint != self.fingerprint(query) {
+            return Err(anyhow::anyhow!(
+                "Cursor belongs to another query. Send the same query and options to get the next page"
+            ));
+        }
+        self.offset = offset.parse().map_err(|_| invalid_cursor())?;
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.columns.is_empty() {
+            return Err(anyhow::anyhow!(
+                "At least one column to search in is required"
+            ));
+        }
+        if self.limit < 1 {
+            return Err(anyhow::anyhow!("Limit should be at least 1"));
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            if !(column.weight.is_finite() && column.weight > 0.0) {
+                return Err(anyhow::anyhow!(
+                    "Weight of column '{}' should be a positive number",
+                    column.column_name
+                ));
+            }
+            if self.columns[..i]
+                .iter()
+                .any(|c| c.column_name == column.column_name)
+            {
+                return Err(anyhow::anyhow!(
+                    "Column '{}' is given more than once",
+                    column.column_name
+                ));
+            }
+        }
+        if let Some(min_score) = self.min_score {
+            if !min_score.is_finite() {
+                return Err(anyhow::anyhow!("min_score should be a finite number"));
+            }
+        }
+        if let Some(mmr) = self.mmr.as_ref() {
+            if !(0.0..=1.0).contains(&mmr.lambda) {
+                return Err(anyhow::anyhow!("MMR lambda should be between 0 and 1"));
+            }
+            if mmr.pool_size == Some(0) {
+                return Err(anyhow::anyhow!("MMR pool size should be at least 1"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the options against the limit cap of a server, so that a request can't make it
+    /// fetch and re-rank arbitrarily many candidates.
+    pub fn check_limits(&self, max_limit: u32) -> anyhow::Result<()> {
+        if self.limit > max_limit {
+            return Err(anyhow::anyhow!(
+                "Limit should be between 1 and {}",
+                max_limit
+            ));
+        }
+        let max_window = max_limit.saturating_mul(MAX_PAGES);
+        if self.window() > max_window {
+            return Err(anyhow::anyhow!(
+                "offset + limit should be at most {}",
+                max_window
+            ));
+        }
+        let max_pool_size = max_limit.saturating_mul(CANDIDATES_PER_RESULT);
+        if let Some(pool_size) = self.mmr.as_ref().and_then(|mmr| mmr.pool_size) {
+            if pool_size > max_pool_size {
+                return Err(anyhow::anyhow!(
+                    "MMR pool size should be at most {}",
+                    max_pool_size
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the JSON value of a column satisfies a filter: equal to it,
+/// or to one of its elements if the filter is an array. Numbers are compared by value.
+pub fn filter_matches(value: &serde_json::Value, filter: &serde_json::Value) -> bool {
+    let equal = |a: &serde_json::Value, b: &serde_json::Value| match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    };
+    match filter {
+        serde_json::Value::Array(options) => options.iter().any(|option| equal(value, option)),
+        _ => equal(value, filter),
+    }
+}
+
+/// Days from 0001-01-01 to 1970-01-01, which DuckDB dates count from.
+const UNIX_EPOCH_DAYS: i32 = 719_163;
+
+/// Converts a value read from DuckDB into JSON.
+/// Types without a natural JSON representation are converted to their debug representation.
+pub fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(v) => serde_json::json!(v),
+        Value::TinyInt(v) => serde_json::json!(v),
+        Value::SmallInt(v) => serde_json::json!(v),
+        Value::Int(v) => serde_json::json!(v),
+        Value::BigInt(v) => serde_json::json!(v),
+        Value::UTinyInt(v) => serde_json::json!(v),
+        Value::USmallInt(v) => serde_json::json!(v),
+        Value::UInt(v) => serde_json::json!(v),
+        Value::UBigInt(v) => serde_json::json!(v),
+        Value::HugeInt(v) => serde_json::json!(v.to_string()),
+        Value::Float(v) => serde_json::json!(v),
+        Value::Double(v) => serde_json::json!(v),
+        Value::Decimal(v) => serde_json::json!(v.to_string()),
+        Value::Text(v) | Value::Enum(v) => serde_json::json!(v),
+        Value::List(values) | Value::Array(values) => {
+            serde_json::Value::Array(values.iter().map(value_to_json).collect())
+        }
+        Value::Struct(fields) => serde_json::Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), value_to_json(value)))
+                .collect(),
+        ),
+        Value::Union(value) => value_to_json(value),
+        Value::Date32(days) => match NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAYS) {
+            Some(date) => serde_json::json!(date.format("%Y-%m-%d").to_string()),
+            None => serde_json::json!(format!("{:?}", value)),
+        },
+        Value::Timestamp(unit, v) => match DateTime::from_timestamp_micros(unit.to_micros(*v)) {
+            Some(timestamp) => serde_json::json!(timestamp
+                .naive_utc()
+                .format("%Y-%m-%dT%H:%M:%S%.f")
+                .to_string()),
+            None => serde_json::json!(format!("{:?}", value)),
+        },
+        Value::Time64(unit, v) => {
+            let micros = unit.to_micros(*v);
+            match NaiveTime::from_num_seconds_from_midnight_opt(
+                (micros / 1_000_000) as u32,
+                (micros % 1_000_000) as u32 * 1000,
+            ) {
+                Some(time) => serde_json::json!(time.format("%H:%M:%S%.f").to_string()),
+                None => serde_json::json!(format!("{:?}", value)),
+            }
+        }
+        other => serde_json::json!(format!("{:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collection_files_state, value_to_json, MmrOptions, SearchColumn, SearchOptions};
+    use duckdb::types::{OrderedMap, TimeUnit, Value};
+    use std::fs;
+
+    #[test]
+    fn test_collection_files_state() {
+        let dir = std::env::temp_dir().join("test_collection_files_state");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("index").join("text")).unwrap();
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        fs::write(dir.join("index").join("text").join("index.bin"), "abc").unwrap();
+
+        let state = collection_files_state(&dir);
+        assert_eq!(state.len(), 2);
+        // server locks are not part of the collection
+        fs::write(dir.join("server.1.lock"), "1").unwrap();
+        assert_eq!(collection_files_state(&dir), state);
+
+        fs::write(dir.join("index").join("text").join("index.bin"), "abcd").unwrap();
+        assert_ne!(collection_files_state(&dir), state);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_cursor() {
+        let mut options = SearchOptions::default();
+        options.columns = vec![SearchColumn::new("text")];
+        options.limit = 5;
+
+        // a partial page is the last one
+        assert!(options.next_cursor("query", 4).is_none());
+
+        let cursor = options.next_cursor("query", 5).unwrap();
+        let mut next_options = options.clone();
+        next_options.apply_cursor("query", &cursor).unwrap();
+        assert_eq!(next_options.offset, 5);
+        assert_eq!(next_options.window(), 10);
+
+        let cursor = next_options.next_cursor("query", 5).unwrap();
+        next_options.apply_cursor("query", &cursor).unwrap();
+        assert_eq!(next_options.offset, 10);
+
+        // the page size can change between pages, but not the search itself
+        options.limit = 20;
+        assert!(options.apply_cursor("query", &cursor).is_ok());
+        assert!(options.apply_cursor("another query", &cursor).is_err());
+        options.min_score = Some(0.5);
+        assert!(options.apply_cursor("query", &cursor).is_err());
+        assert!(options.apply_cursor("query", "not a cursor").is_err());
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let mut options = SearchOptions::default();
+        options.columns = vec![SearchColumn::new("text")];
+        options.limit = 10;
+        assert!(options.check_limits(10).is_ok());
+        assert!(options.check_limits(5).is_err());
+
+        // pages can't go deeper than 10 times the limit cap, even with cursors
+        options.offset = 90;
+        assert!(options.check_limits(10).is_ok());
+        let cursor = options.next_cursor("query", 10).unwrap();
+        options.apply_cursor("query", &cursor).unwrap();
+        assert!(options.check_limits(10).is_err());
+        options.offset = u32::MAX;
+        assert!(options.check_limits(10).is_err());
+
+        options.offset = 0;
+        options.mmr = Some(MmrOptions {
+            lambda: 0.5,
+            pool_size: Some(41),
+        });
+        assert!(options.check_limits(10).is_err());
+        options.mmr = Some(MmrOptions {
+            lambda: 0.5,
+            pool_size: Some(40),
+        });
+        assert!(options.check_limits(10).is_ok());
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        assert_eq!(value_to_json(&Value::Null), serde_json::Value::Null);
+        assert_eq!(value_to_json(&Value::BigInt(-3)), serde_json::json!(-3));
+        assert_eq!(
+            value_to_json(&Value::HugeInt(1 << 100)),
+            serde_json::json!((1i128 << 100).to_string())
+        );
+
+        let list = Value::List(vec![Value::Text(String::from("a")), Value::Null]);
+        assert_eq!(value_to_json(&list), serde_json::json!(["a", null]));
+
+        let nested = Value::Struct(OrderedMap::from(vec![
+            (String::from("x"), Value::Int(1)),
+            (String::from("tags"), list),
+            (String::from("missing"), Value::Null),
+        ]));
+        assert_eq!(
+            value_to_json(&nested),
+            serde_json::json!({"x": 1, "tags": ["a", null], "missing": null})
+        );
+
+        assert_eq!(
+            value_to_json(&Value::Date32(0)),
+            serde_json::json!("1970-01-01")
+        );
+        assert_eq!(
+            value_to_json(&Value::Date32(19_724)),
+            serde_json::json!("2024-01-02")
+        );
+        assert_eq!(
+            value_to_json(&Value::Timestamp(TimeUnit::Second, 1_704_164_645)),
+            serde_json::json!("2024-01-02T03:04:05")
+        );
+        assert_eq!(
+            value_to_json(&Value::Time64(TimeUnit::Microsecond, 3_723_500_000)),
+            serde_json::json!("01:02:03.500")
+        );
+    }
 }
@@ -0,0 +1,364 @@
+use super::collection_utils::{collection_dir, copy_dir, home_dir, server_lock_holder};
+use duckdb::Connection;
+use log::{info, warn};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Version of the on-disk layout written by this binary.
+/// Bump it together with a new entry in `migrations()` whenever `config.json`, the DuckDB tables
+/// or the index layout change in a way that older collections need to be upgraded.
+pub const CURRENT_SERIALIZATION_VERSION: u32 = 2;
+
+/// Upgrades a collection from `from_version` to `from_version + 1` in place.
+pub struct Migration {
+    pub from_version: u32,
+    pub description: &'static str,
+    /// whether collections need it before they can be opened. Migrations that only write out
+    /// what this binary already assumes when reading older collections are not required
+    pub required: bool,
+    /// Receives the collection directory, its raw `config.json` and a connection to its DuckDB file.
+    /// The config is written back after all the migrations succeed.
+    pub apply: fn(&Path, &mut Value, &Connection) -> anyhow::Result<()>,
+}
+
+/// All the migrations in order. Each one must upgrade from the version the previous one upgrades to.
+pub fn migrations() -> Vec<Migration> {
+    vec![Migration {
+        from_version: 1,
+        description: "record list_mode and virtual_fields in config.json",
+        required: false,
+        apply: add_list_mode_and_virtual_fields,
+    }]
+}
+
+/// Version 2 added `list_mode` and `virtual_fields` to the config. Their defaults keep
+/// the previous behavior, so older collections only get them written out explicitly.
+fn add_list_mode_and_virtual_fields(
+    _dir: &Path,
+    config: &mut Value,
+    _conn: &Connection,
+) -> anyhow::Result<()> {
+    let config = config
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("config.json is not a JSON object"))?;
+    config
+        .entry("list_mode")
+        .or_insert_with(|| Value::from("join"));
+    config
+        .entry("virtual_fields")
+        .or_insert_with(|| Value::Array(vec![]));
+    Ok(())
+}
+
+/// Result of migrating a collection.
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// descriptions of the applied migrations in order
+    pub applied: Vec<&'static str>,
+    /// copy of the collection taken before migrating it
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// Serialization version recorded in a raw `config.json`.
+/// Configs written before the field was introduced are version 1.
+fn config_version(config: &Value) -> u32 {
+    config
+        .get("serialization_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1) as u32
+}
+
+/// Refuses collections written by a newer binary, and older ones that need a required migration.
+/// Older collections that only miss optional migrations are opened as they are.
+pub fn check_serialization_version(name: &str, version: u32) -> anyhow::Result<()> {
+    if version > CURRENT_SERIALIZATION_VERSION {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' has serialization version {}, but this version of letsearch supports up to {}. Upgrade letsearch to open it",
+            name,
+            version,
+            CURRENT_SERIALIZATION_VERSION
+        ));
+    }
+
+    let migrations = migrations();
+    let pending = pending_migrations(&migrations, version, CURRENT_SERIALIZATION_VERSION)?;
+    if pending.iter().any(|m| m.required) {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' has serialization version {} and needs to be upgraded to {}. Run `letsearch migrate {}` first",
+            name,
+            version,
+            CURRENT_SERIALIZATION_VERSION,
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Migrations needed to upgrade from `version` to `target_version`.
+fn pending_migrations(
+    migrations: &[Migration],
+    version: u32,
+    target_version: u32,
+) -> anyhow::Result<Vec<&Migration>> {
+    (version..target_version)
+        .map(|from_version| {
+            migrations
+                .iter()
+                .find(|m| m.from_version == from_version)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No migration found from serialization version {}",
+                        from_version
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Descriptions of the migrations that `migrate_collection` would apply to the collection `name`.
+pub fn pending_collection_migrations(name: &str) -> anyhow::Result<Vec<&'static str>> {
+    let config = read_raw_config(&collection_dir(name))?;
+    let version = config_version(&config);
+    if version > CURRENT_SERIALIZATION_VERSION {
+        check_serialization_version(name, version)?;
+    }
+    let migrations = migrations();
+    let pending = pending_migrations(&migrations, version, CURRENT_SERIALIZATION_VERSION)?;
+
+    Ok(pending.iter().map(|m| m.description).collect())
+}
+
+fn read_raw_config(dir: &Path) -> anyhow::Result<Value> {
+    let config_path = dir.join("config.json");
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Collection config {} does not exist",
+            config_path.display()
+        ));
+    }
+
+    Ok(serde_json::from_reader(File::open(config_path)?)?)
+}
+
+/// Upgrades the collection `name` to `CURRENT_SERIALIZATION_VERSION` in place.
+///
+/// The collection is copied to `LETSEARCH_HOME/backups` first,
+/// and restored from that copy if any of the migrations fail.
+pub fn migrate_collection(name: &str) -> anyhow::Result<MigrationReport> {
+    run_migrations(name, &migrations(), CURRENT_SERIALIZATION_VERSION)
+}
+
+fn run_migrations(
+    name: &str,
+    migrations: &[Migration],
+    target_version: u32,
+) -> anyhow::Result<MigrationReport> {
+    let dir = collection_dir(name);
+    let mut config = read_raw_config(&dir)?;
+    let version = config_version(&config);
+    if version > target_version {
+        check_serialization_version(name, version)?;
+    }
+
+    let pending = pending_migrations(migrations, version, target_version)?;
+    if pending.is_empty() {
+        return Ok(MigrationReport {
+            from_version: version,
+            to_version: version,
+            applied: vec![],
+            backup_dir: None,
+        });
+    }
+
+    if let Some(pid) = server_lock_holder(name) {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' is currently being served by process {}. Stop the server before migrating it",
+            name,
+            pid
+        ));
+    }
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let mut backup_dir = home_dir()
+        .join("backups")
+        .join(format!("{}-v{}-{}", name, version, timestamp));
+    let mut suffix = 1;
+    while backup_dir.exists() {
+        backup_dir = home_dir()
+            .join("backups")
+            .join(format!("{}-v{}-{}-{}", name, version, timestamp, suffix));
+        suffix += 1;
+    }
+    copy_dir(&dir, &backup_dir)?;
+    info!(
+        "Collection '{}' backed up to {}",
+        name,
+        backup_dir.display()
+    );
+
+    let result = apply_migrations(&dir, &mut config, &pending);
+    if let Err(e) = result {
+        warn!(
+            "Migrating collection '{}' failed, restoring it from the backup",
+            name
+        );
+        fs::remove_dir_all(&dir)?;
+        copy_dir(&backup_dir, &dir)?;
+        return Err(e);
+    }
+
+    info!(
+        "Collection '{}' migrated from serialization version {} to {}",
+        name, version, target_version
+    );
+
+    Ok(MigrationReport {
+        from_version: version,
+        to_version: target_version,
+        applied: pending.iter().map(|m| m.description).collect(),
+        backup_dir: Some(backup_dir),
+    })
+}
+
+fn apply_migrations(dir: &Path, config: &mut Value, pending: &[&Migration]) -> anyhow::Result<()> {
+    let db_path = config
+        .get("db_path")
+        .and_then(|v| v.as_str())
+        .unwrap_or("data.db")
+        .to_string();
+    {
+        let conn = Connection::open(dir.join(db_path))?;
+        for migration in pending {
+            info!(
+                "Applying migration from version {}: {}",
+                migration.from_version, migration.description
+            );
+            (migration.apply)(dir, config, &conn)?;
+            config["serialization_version"] = Value::from(migration.from_version + 1);
+        }
+    }
+
+    fs::write(dir.join("config.json"), serde_json::to_string(config)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_serialization_version, run_migrations, Migration, CURRENT_SERIALIZATION_VERSION,
+    };
+    use crate::collection::collection_utils::collection_dir;
+    use duckdb::Connection;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::Path;
+
+    fn rename_text_column(
+        _dir: &Path,
+        config: &mut Value,
+        conn: &Connection,
+    ) -> anyhow::Result<()> {
+        conn.execute_batch("ALTER TABLE test_migrations RENAME COLUMN text TO content;")?;
+        config["index_columns"] = serde_json::json!(["content"]);
+        Ok(())
+    }
+
+    fn fail(_dir: &Path, _config: &mut Value, conn: &Connection) -> anyhow::Result<()> {
+        conn.execute_batch("DROP TABLE test_migrations;")?;
+        Err(anyhow::anyhow!("migration failed"))
+    }
+
+    fn write_v1_collection(dir: &Path) {
+        if dir.exists() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+        fs::create_dir_all(dir).unwrap();
+        // configs written before serialization_version was introduced are version 1
+        fs::write(
+            dir.join("config.json"),
+            r#"{"name": "test_migrations", "index_columns": ["text"]}"#,
+        )
+        .unwrap();
+        let conn = Connection::open(dir.join("data.db")).unwrap();
+        conn.execute_batch("CREATE TABLE test_migrations AS SELECT 'hello' AS text;")
+            .unwrap();
+    }
+
+    fn column_names(dir: &Path) -> Vec<String> {
+        let conn = Connection::open(dir.join("data.db")).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT column_name FROM information_schema.columns WHERE table_name = 'test_migrations';")
+            .unwrap();
+        let names = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        names
+    }
+
+    #[test]
+    fn test_migrations() {
+        let dir = collection_dir("test_migrations");
+        write_v1_collection(&dir);
+        let migrations = vec![
+            Migration {
+                from_version: 1,
+                description: "rename text to content",
+                required: true,
+                apply: rename_text_column,
+            },
+            Migration {
+                from_version: 2,
+                description: "fail",
+                required: true,
+                apply: fail,
+            },
+        ];
+
+        // a failing migration leaves the collection as it was
+        assert!(run_migrations("test_migrations", &migrations, 3).is_err());
+        assert_eq!(column_names(&dir), vec!["text"]);
+
+        // no migration to reach version 5
+        assert!(run_migrations("test_migrations", &migrations, 5).is_err());
+
+        let report = run_migrations("test_migrations", &migrations, 2).unwrap();
+        assert_eq!(report.applied, vec!["rename text to content"]);
+        let backup_dir = report.backup_dir.unwrap();
+        assert!(backup_dir.join("data.db").exists());
+        assert_eq!(column_names(&dir), vec!["content"]);
+        let config: Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("config.json")).unwrap()).unwrap();
+        assert_eq!(config["serialization_version"], 2);
+        assert_eq!(config["index_columns"], serde_json::json!(["content"]));
+
+        // already up to date
+        let report = run_migrations("test_migrations", &migrations, 2).unwrap();
+        assert!(report.applied.is_empty());
+
+        assert!(check_serialization_version("test", CURRENT_SERIALIZATION_VERSION).is_ok());
+        assert!(check_serialization_version("test", CURRENT_SERIALIZATION_VERSION + 1).is_err());
+        // collections from before version 2 can be opened without migrating them
+        assert!(check_serialization_version("test", 1).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+        for entry in fs::read_dir(backup_dir.parent().unwrap()).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("test_migrations-")
+            {
+                fs::remove_dir_all(path).unwrap();
+            }
+        }
+    }
+}
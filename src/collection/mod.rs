@@ -1,4 +1,6 @@
+pub mod collection_archive;
 pub mod collection_manager;
 pub mod collection_type;
 pub mod collection_utils;
+pub mod migrations;
 pub mod vector_index;
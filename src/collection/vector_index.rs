@@ -1,11 +1,16 @@
+use crate::metrics::VECTOR_SEARCH_DURATION;
 use anyhow;
 use log::{debug, info};
 use rayon::prelude::*;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fs, u64, usize};
 use usearch::{new_index, Index, IndexOptions, VectorType};
+use utoipa::ToSchema;
 
 #[derive(Serialize)]
 pub struct SimilarityResult {
@@ -13,6 +18,84 @@ pub struct SimilarityResult {
     pub score: f32,
 }
 
+/// Metadata stored in the header of a serialized usearch index.
+#[derive(Serialize, Clone, Debug)]
+pub struct IndexHeader {
+    pub version: String,
+    pub metric: String,
+    pub scalar_kind: String,
+    pub dimensions: u64,
+    pub count: u64,
+    pub multi: bool,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct VectorIndexStats {
+    pub size: usize,
+    pub capacity: usize,
+    pub dimensions: usize,
+    pub connectivity: usize,
+    pub metric: String,
+    pub scalar_kind: String,
+    pub memory_usage: usize,
+    pub disk_size: u64,
+}
+
+fn metric_name(kind: u8) -> String {
+    match kind {
+        b'i' => "ip",
+        b'c' => "cos",
+        b'e' => "l2sq",
+        b'p' => "pearson",
+        b'h' => "haversine",
+        b'd' => "divergence",
+        b'b' => "hamming",
+        b't' => "tanimoto",
+        b's' => "sorensen",
+        b'j' => "jaccard",
+        _ => "unknown",
+    }
+    .to_string()
+}
+
+fn scalar_kind_name(kind: u8) -> String {
+    match kind {
+        1 => "b1",
+        4 => "bf16",
+        10 => "f64",
+        11 => "f32",
+        12 => "f16",
+        13 => "f8",
+        23 => "i8",
+        _ => "unknown",
+    }
+    .to_string()
+}
+
+/// Reads the header of an `index.bin` file written by usearch
+/// to get the options that cannot be queried from a loaded index.
+pub fn read_index_header(index_path: &Path) -> anyhow::Result<IndexHeader> {
+    let mut header = [0u8; 42];
+    File::open(index_path)?.read_exact(&mut header)?;
+    if &header[..7] != b"usearch" {
+        return Err(anyhow::anyhow!(
+            "{} is not a usearch index",
+            index_path.display()
+        ));
+    }
+
+    let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+    let u64_at = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+    Ok(IndexHeader {
+        version: format!("{}.{}.{}", u16_at(7), u16_at(9), u16_at(11)),
+        metric: metric_name(header[13]),
+        scalar_kind: scalar_kind_name(header[14]),
+        count: u64_at(17),
+        dimensions: u64_at(33),
+        multi: header[41] != 0,
+    })
+}
+
 struct PtrBox<T: VectorType> {
     ptr: *const T,
 }
@@ -75,6 +158,49 @@ impl VectorIndex {
         Ok(())
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn stats(&self) -> anyhow::Result<VectorIndexStats> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Vector index is not initialized"))?;
+        let index_path = self.path.join("index.bin");
+        let (metric, scalar_kind) = match read_index_header(&index_path) {
+            Ok(header) => (header.metric, header.scalar_kind),
+            Err(_) => (String::from("unknown"), String::from("unknown")),
+        };
+
+        Ok(VectorIndexStats {
+            size: index.size(),
+            capacity: index.capacity(),
+            dimensions: index.dimensions(),
+            connectivity: index.connectivity(),
+            metric,
+            scalar_kind,
+            memory_usage: index.memory_usage(),
+            disk_size: fs::metadata(&index_path).map(|m| m.len()).unwrap_or(0),
+        })
+    }
+
+    /// Returns the vector stored for `key` as f32, or `None` if `key` is not in the index.
+    pub fn get_vector(&self, key: u64) -> anyhow::Result<Option<Vec<f32>>> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Vector index is not initialized"))?;
+        let mut vectors: Vec<f32> = Vec::new();
+        if index.export(key, &mut vectors)? == 0 {
+            return Ok(None);
+        }
+
+        // multi-vector indexes may have several vectors per key, the first one is returned
+        vectors.truncate(index.dimensions());
+        Ok(Some(vectors))
+    }
+
     pub async fn add<T: VectorType>(
         &self,
         keys: &Vec<u64>,
@@ -102,7 +228,7 @@ impl VectorIndex {
         Ok(())
     }
 
-    pub async fn search<T: VectorType>(
+    pub fn search<T: VectorType>(
         &self,
         vector: *const T,
         vector_dim: usize,
@@ -111,7 +237,9 @@ impl VectorIndex {
         let query_vector: &[T] = unsafe { std::slice::from_raw_parts(vector, vector_dim) };
         let index = self.index.as_ref().unwrap();
 
+        let start = Instant::now();
         let matches = index.search(query_vector, count)?;
+        VECTOR_SEARCH_DURATION.observe(start.elapsed().as_secs_f64());
         let results: Vec<SimilarityResult> = matches
             .keys
             .iter()
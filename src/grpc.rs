@@ -0,0 +1,386 @@
+use crate::auth::{ApiKey, ApiKeys, Role};
+use crate::collection::collection_manager::{CollectionManager, SearchError};
+use crate::collection::collection_utils::{MmrOptions, SearchColumn, SearchOptions};
+use crate::serve::check_embedding_inputs;
+use actix_web::web;
+use log::info;
+use tokio::sync::RwLock;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("letsearch.v1");
+}
+
+use proto::letsearch_server::{Letsearch, LetsearchServer};
+
+/// Maximum number of searches in a `BatchSearch` call.
+const MAX_BATCH_SEARCHES: usize = 100;
+
+/// gRPC service sharing the collections of the REST server.
+pub struct GrpcService {
+    manager: web::Data<RwLock<CollectionManager>>,
+    /// maximum number of results per search request
+    max_limit: u32,
+    /// keys required like for the REST API. Every request is allowed without them
+    api_keys: Option<web::Data<ApiKeys>>,
+}
+
+impl GrpcService {
+    pub fn new(
+        manager: web::Data<RwLock<CollectionManager>>,
+        max_limit: u32,
+        api_keys: Option<web::Data<ApiKeys>>,
+    ) -> Self {
+        GrpcService {
+            manager,
+            max_limit,
+            api_keys,
+        }
+    }
+
+    /// Checks the API key sent in the `authorization: Bearer <key>` or `x-api-key` metadata.
+    /// Returns `None` if authentication is disabled.
+    #[allow(clippy::result_large_err)]
+    fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        collection: Option<&str>,
+    ) -> Result<Option<ApiKey>, Status> {
+        let api_keys = match self.api_keys.as_ref() {
+            Some(api_keys) => api_keys,
+            None => return Ok(None),
+        };
+        let api_key = api_keys
+            .authenticate_metadata(request.metadata())
+            .ok_or_else(|| Status::unauthenticated("Missing or invalid API key"))?;
+        if !api_key.allows(collection, Role::Read) {
+            return Err(Status::permission_denied(format!(
+                "API key '{}' is not allowed to read collection '{}'",
+                api_key.name,
+                collection.unwrap_or_default()
+            )));
+        }
+
+        Ok(Some(api_key.clone()))
+    }
+
+    async fn run_search(&self, req: proto::SearchRequest) -> Result<proto::SearchResponse, Status> {
+        let options = search_options(&req, self.max_limit)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let results = self
+            .manager
+            .read()
+            .await
+            .search(
+                req.collection_name.clone(),
+                req.query.clone(),
+                options.clone(),
+            )
+            .await
+            .map_err(|e| match e {
+                SearchError::CollectionNotFound(_) => Status::not_found(e.to_string()),
+                SearchError::InvalidOptions(_) => Status::invalid_argument(e.to_string()),
+                SearchError::Failed(_) => Status::internal(e.to_string()),
+            })?;
+
+        Ok(proto::SearchResponse {
+            next_cursor: options.next_cursor(&req.query, results.len()),
+            results: results
+                .into_iter()
+                .map(|result| proto::SearchResult {
+                    content: result.content,
+                    key: result.key,
+                    score: result.score,
+                    matched_columns: result.matched_columns,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Builds the options of a search request like the REST API does for `QueryRequest`.
+fn search_options(req: &proto::SearchRequest, max_limit: u32) -> anyhow::Result<SearchOptions> {
+    let columns = match (&req.column_name, req.columns.is_empty()) {
+        (Some(column_name), true) => vec![SearchColumn::new(column_name)],
+        (None, false) => req
+            .columns
+            .iter()
+            .map(|column| SearchColumn {
+                column_name: column.column_name.clone(),
+                weight: column.weight.unwrap_or(1.0),
+            })
+            .collect(),
+        (Some(_), false) => {
+            return Err(anyhow::anyhow!(
+                "Provide either column_name or columns, not both"
+            ))
+        }
+        (None, true) => {
+            return Err(anyhow::anyhow!(
+                "Provide the column to search in with column_name or columns"
+            ))
+        }
+    };
+
+    let mut options = SearchOptions {
+        columns,
+        limit: req.limit.unwrap_or(10),
+        fusion: match req.fusion.as_deref() {
+            Some(fusion) => fusion.parse()?,
+            None => Default::default(),
+        },
+        mmr: req.mmr.as_ref().map(|mmr| MmrOptions {
+            lambda: mmr.lambda,
+            pool_size: mmr.pool_size,
+        }),
+        collapse_column: req.collapse_column.clone(),
+        min_score: req.min_score,
+        offset: req.offset.unwrap_or(0),
+        filters: req
+            .filters
+            .iter()
+            .map(|(column, value)| {
+                let value = serde_json::from_str(value)
+                    .unwrap_or(serde_json::Value::String(value.to_string()));
+                (column.to_string(), value)
+            })
+            .collect(),
+    };
+    if let Some(cursor) = req.cursor.as_ref() {
+        if req.offset.is_some() {
+            return Err(anyhow::anyhow!("Provide either offset or cursor, not both"));
+        }
+        options.apply_cursor(&req.query, cursor)?;
+    }
+    options.validate()?;
+    options.check_limits(max_limit)?;
+
+    Ok(options)
+}
+
+#[tonic::async_trait]
+impl Letsearch for GrpcService {
+    async fn list_collections(
+        &self,
+        request: Request<proto::ListCollectionsRequest>,
+    ) -> Result<Response<proto::ListCollectionsResponse>, Status> {
+        let api_key = self.authorize(&request, None)?;
+        let configs = self.manager.read().await.get_collection_configs().await;
+        let collections = configs
+            .into_iter()
+            .filter(|config| {
+                api_key
+                    .as_ref()
+                    .is_none_or(|key| key.can_access(&config.name))
+            })
+            .map(|config| proto::Collection {
+                name: config.name,
+                index_columns: config.index_columns,
+            })
+            .collect();
+
+        Ok(Response::new(proto::ListCollectionsResponse {
+            collections,
+        }))
+    }
+
+    async fn get_collection(
+        &self,
+        request: Request<proto::GetCollectionRequest>,
+    ) -> Result<Response<proto::Collection>, Status> {
+        let collection_name = request.get_ref().collection_name.clone();
+        self.authorize(&request, Some(&collection_name))?;
+        let config = self
+            .manager
+            .read()
+            .await
+            .get_collection_config(collection_name)
+            .await
+            .map_err(|e| Status::not_found(e.to_string()))?;
+
+        Ok(Response::new(proto::Collection {
+            name: config.name,
+            index_columns: config.index_columns,
+        }))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        self.authorize(&request, Some(&request.get_ref().collection_name))?;
+        Ok(Response::new(self.run_search(request.into_inner()).await?))
+    }
+
+    async fn batch_search(
+        &self,
+        request: Request<proto::BatchSearchRequest>,
+    ) -> Result<Response<proto::BatchSearchResponse>, Status> {
+        for req in request.get_ref().requests.iter() {
+            self.authorize(&request, Some(&req.collection_name))?;
+        }
+        let requests = request.into_inner().requests;
+        if requests.len() > MAX_BATCH_SEARCHES {
+            return Err(Status::invalid_argument(format!(
+                "A batch can have up to {} searches",
+                MAX_BATCH_SEARCHES
+            )));
+        }
+
+        let mut responses = Vec::with_capacity(requests.len());
+        for req in requests {
+            responses.push(self.run_search(req).await?);
+        }
+
+        Ok(Response::new(proto::BatchSearchResponse { responses }))
+    }
+
+    async fn embed(
+        &self,
+        request: Request<proto::EmbedRequest>,
+    ) -> Result<Response<proto::EmbedResponse>, Status> {
+        self.authorize(&request, None)?;
+        let req = request.into_inner();
+        check_embedding_inputs(&req.input).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let manager = self.manager.read().await;
+        manager
+            .find_model(req.model.as_str())
+            .await
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        let embeddings = manager
+            .embed(
+                req.model.as_str(),
+                req.input.iter().map(|text| text.as_str()).collect(),
+            )
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(proto::EmbedResponse {
+            model: embeddings.model,
+            embeddings: embeddings
+                .embeddings
+                .into_iter()
+                .map(|values| proto::Embedding { values })
+                .collect(),
+            token_count: embeddings.token_count as u64,
+        }))
+    }
+}
+
+/// Serves the gRPC API on `host:port` until the process stops.
+pub async fn run_grpc_server(
+    host: String,
+    port: i32,
+    manager: web::Data<RwLock<CollectionManager>>,
+    max_limit: u32,
+    api_keys: Option<web::Data<ApiKeys>>,
+) -> anyhow::Result<()> {
+    let address = tokio::net::lookup_host(format!("{host}:{port}"))
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Cannot resolve {}:{}", host, port))?;
+    info!("gRPC server listening on {}", address);
+    tonic::transport::Server::builder()
+        .add_service(LetsearchServer::new(GrpcService::new(
+            manager, max_limit, api_keys,
+        )))
+        .serve(address)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::letsearch_server::Letsearch;
+    use super::{proto, search_options, GrpcService};
+    use crate::collection::collection_manager::CollectionManager;
+    use actix_web::web;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request};
+
+    #[test]
+    fn test_search_options() {
+        let mut req = proto::SearchRequest {
+            collection_name: String::from("test"),
+            query: String::from("query"),
+            column_name: Some(String::from("text")),
+            ..Default::default()
+        };
+        req.filters
+            .insert(String::from("year"), String::from("[2020, 2021]"));
+        req.filters
+            .insert(String::from("document_id"), String::from("a b"));
+        let options = search_options(&req, 100).unwrap();
+        assert_eq!(options.columns[0].column_name, "text");
+        assert_eq!(options.limit, 10);
+        assert_eq!(options.filters["year"], serde_json::json!([2020, 2021]));
+        assert_eq!(options.filters["document_id"], "a b");
+
+        req.limit = Some(200);
+        assert!(search_options(&req, 100).is_err());
+        req.limit = None;
+        req.column_name = None;
+        let error = search_options(&req, 100).unwrap_err();
+        assert!(error.to_string().starts_with("Provide the column"));
+        req.column_name = Some(String::from("text"));
+        req.columns = vec![proto::SearchColumn {
+            column_name: String::from("title"),
+            weight: Some(2.0),
+        }];
+        assert!(search_options(&req, 100).is_err());
+        req.column_name = None;
+        req.fusion = Some(String::from("rrf"));
+        let options = search_options(&req, 100).unwrap();
+        assert_eq!(options.columns[0].weight, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_service() {
+        let manager = web::Data::new(RwLock::new(CollectionManager::new(None)));
+        let service = GrpcService::new(manager, 100, None);
+
+        let response = service
+            .list_collections(Request::new(proto::ListCollectionsRequest {}))
+            .await
+            .unwrap();
+        assert!(response.into_inner().collections.is_empty());
+
+        let status = service
+            .get_collection(Request::new(proto::GetCollectionRequest {
+                collection_name: String::from("missing"),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        let status = service
+            .embed(Request::new(proto::EmbedRequest {
+                model: String::from("mys/minilm"),
+                input: vec![],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = service
+            .embed(Request::new(proto::EmbedRequest {
+                model: String::from("mys/minilm"),
+                input: vec![String::from("text"), String::new()],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = service
+            .embed(Request::new(proto::EmbedRequest {
+                model: String::from("mys/minilm"),
+                input: vec![String::from("text")],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+}
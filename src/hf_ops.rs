@@ -1,16 +1,62 @@
 use crate::collection::collection_utils::home_dir;
+use crate::model::model_utils::{resolve_local_model, ModelMetadata, MODEL_METADATA_FILE};
 use anyhow;
+use base64::Engine;
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
 use reqwest;
-use reqwest::header::CONTENT_LENGTH;
 use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, RANGE};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// Base URL of the HuggingFace Hub.
+/// It can be pointed at a Hub-compatible mirror with the `HF_ENDPOINT` environment variable.
+pub fn hf_endpoint() -> String {
+    std::env::var("HF_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_HF_ENDPOINT.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Set with `set_offline`, e.g. by the `--offline` flag.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Turns offline mode on for the whole process, like setting `LETSEARCH_OFFLINE`, without touching the environment.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Offline mode is enabled with `set_offline` or by setting `LETSEARCH_OFFLINE` or `HF_HUB_OFFLINE` to a truthy value.
+/// In offline mode, models and datasets are resolved only from `LETSEARCH_HOME`.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+        || ["LETSEARCH_OFFLINE", "HF_HUB_OFFLINE"].iter().any(|var| {
+            std::env::var(var)
+                .map(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false)
+        })
+}
+
+pub fn offline_error(what: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Cannot {} in offline mode. Drop --offline and unset LETSEARCH_OFFLINE / HF_HUB_OFFLINE to access the network",
+        what
+    )
+}
+
 #[derive(Deserialize, Debug)]
 #[allow(non_snake_case)]
 #[allow(dead_code)]
@@ -60,13 +106,39 @@ pub struct BlobLfsInfo {
 
 #[allow(dead_code)]
 pub async fn get_model_info(repo_id: &str, files_metadata: bool) -> anyhow::Result<ModelInfo> {
+    get_model_info_from(hf_endpoint().as_str(), repo_id, None, files_metadata, None).await
+}
+
+async fn get_model_info_from(
+    endpoint: &str,
+    repo_id: &str,
+    revision: Option<&str>,
+    files_metadata: bool,
+    token: Option<String>,
+) -> anyhow::Result<ModelInfo> {
+    if is_offline() {
+        return Err(offline_error("fetch model info"));
+    }
+
+    let revision_path = match revision {
+        Some(revision) => format!("/revision/{}", encode_revision(revision)),
+        None => String::new(),
+    };
     let metadata_param = if files_metadata { "?blobs=true" } else { "" };
     let url = format!(
-        "https://huggingface.co/api/models/{}{}",
-        repo_id, metadata_param
+        "{}/api/models/{}{}{}",
+        endpoint, repo_id, revision_path, metadata_param
     );
     let client = reqwest::Client::builder().build()?;
-    let response = client.get(&url).send().await?;
+    let response = match token.as_ref() {
+        Some(token) => client.get(&url).header(
+            AUTHORIZATION,
+            HeaderValue::from_str(format!("BEARER {token}").as_str()).unwrap(),
+        ),
+        None => client.get(&url),
+    }
+    .send()
+    .await?;
     if !response.status().is_success() {
         return Err(anyhow::anyhow!(
             "Failed to fetch model info: {}",
@@ -77,8 +149,16 @@ pub async fn get_model_info(repo_id: &str, files_metadata: bool) -> anyhow::Resu
     Ok(model_info)
 }
 
-async fn get_models(filter: &str, token: Option<String>) -> anyhow::Result<Vec<Model>> {
-    let url = format!("https://huggingface.co/api/models?filter={}", filter);
+async fn get_models(
+    endpoint: &str,
+    filter: &str,
+    token: Option<String>,
+) -> anyhow::Result<Vec<Model>> {
+    if is_offline() {
+        return Err(offline_error("list models on HuggingFace Hub"));
+    }
+
+    let url = format!("{}/api/models?filter={}", endpoint, filter);
     let client = reqwest::Client::builder().build()?;
     let response = match token.as_ref() {
         Some(token) => client.get(&url).header(
@@ -98,74 +178,335 @@ async fn get_models(filter: &str, token: Option<String>) -> anyhow::Result<Vec<M
     Ok(models)
 }
 
+/// Computes the hex-encoded sha256 digest of the file at `path`.
+pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Checks the file at `path` against the size and sha256 reported by the Hub for `expected`.
+pub fn verify_file(path: &Path, expected: &RepoFile) -> anyhow::Result<()> {
+    let lfs = expected.lfs.as_ref();
+    let actual_size = fs::metadata(path)?.len();
+    if let Some(expected_size) = lfs.and_then(|lfs| lfs.size).or(expected.size) {
+        if actual_size != expected_size {
+            return Err(anyhow::anyhow!(
+                "Size mismatch for {}: expected {} bytes, got {} bytes",
+                expected.rfilename,
+                expected_size,
+                actual_size
+            ));
+        }
+    }
+
+    if let Some(expected_sha256) = lfs.and_then(|lfs| lfs.sha256.as_ref()) {
+        let actual_sha256 = sha256_file(path)?;
+        if !actual_sha256.eq_ignore_ascii_case(expected_sha256) {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch for {}: expected sha256 {}, got {}",
+                expected.rfilename,
+                expected_sha256,
+                actual_sha256
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the files of a model repo at `revision` with their sizes and checksums, keyed by file name.
+async fn get_repo_files(
+    endpoint: &str,
+    repo_id: &str,
+    revision: &str,
+    token: Option<String>,
+) -> anyhow::Result<HashMap<String, RepoFile>> {
+    let model_info = get_model_info_from(endpoint, repo_id, Some(revision), true, token).await?;
+    let repo_files = model_info
+        .siblings
+        .unwrap_or_default()
+        .into_iter()
+        .map(|file| (file.rfilename.clone(), file))
+        .collect();
+
+    Ok(repo_files)
+}
+
+/// Downloads `file_name` from `repo_id` into `destination_dir`.
+///
+/// The file is first written to a `.part` file next to the destination,
+/// which is resumed with an HTTP Range request if a previous download was interrupted.
+/// It is renamed to its final path only after it's verified against `expected`, if given.
+/// Partial files are resumed and existing files reused only if they can be verified against `expected`.
 async fn download_file(
+    endpoint: &str,
     repo_id: &str,
+    revision: &str,
     file_name: &str,
     destination_dir: PathBuf,
     token: Option<String>,
+    expected: Option<&RepoFile>,
 ) -> anyhow::Result<String> {
-    if !destination_dir.exists() {
-        fs::create_dir_all(destination_dir.clone())?;
+    let destination_path = destination_dir.join(file_name);
+    if let Some(parent) = destination_path.parent() {
+        fs::create_dir_all(parent)?;
     }
 
-    let destination_path = destination_dir.join(file_name);
     if destination_path.exists() {
-        return Ok(destination_path.to_string_lossy().to_string());
+        match expected.map(|expected| verify_file(&destination_path, expected)) {
+            None | Some(Ok(())) => return Ok(destination_path.to_string_lossy().to_string()),
+            Some(Err(e)) => {
+                warn!("Downloading {} again: {}", file_name, e);
+                fs::remove_file(&destination_path)?;
+            }
+        }
+    }
+
+    if is_offline() {
+        return Err(anyhow::anyhow!(
+            "{} of {} is not in the local cache at {} and offline mode is enabled",
+            file_name,
+            repo_id,
+            destination_dir.display()
+        ));
     }
 
+    let partial_path = destination_dir.join(format!("{}.part", file_name));
+    // without checksums, there is no telling whether a partial file belongs to the same file
+    let resume_from = match expected {
+        Some(_) => fs::metadata(&partial_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0),
+        None => 0,
+    };
+
     let url = format!(
-        "https://huggingface.co/{}/resolve/main/{}",
-        repo_id, file_name
+        "{}/{}/resolve/{}/{}",
+        endpoint,
+        repo_id,
+        encode_revision(revision),
+        file_name
     );
     let client = reqwest::Client::builder().build()?;
 
-    let response = match token.as_ref() {
+    let mut request = match token.as_ref() {
         Some(token) => client.get(&url).header(
             AUTHORIZATION,
             HeaderValue::from_str(format!("BEARER {token}").as_str()).unwrap(),
         ),
         None => client.get(&url),
+    };
+    if resume_from > 0 {
+        request = request.header(RANGE, format!("bytes={}-", resume_from));
     }
-    .send()
-    .await?;
+    let response = request.send().await?;
 
-    if !response.status().is_success() {
+    // A 416 means the partial file already has all the bytes, so it only needs to be verified.
+    if response.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to download file: {}",
+                response.status()
+            ));
+        }
+
+        let (mut file, mut downloaded) = if response.status() == StatusCode::PARTIAL_CONTENT {
+            info!(
+                "Resuming download of {} from byte {}",
+                file_name, resume_from
+            );
+            (
+                OpenOptions::new().append(true).open(&partial_path)?,
+                resume_from,
+            )
+        } else {
+            (File::create(&partial_path)?, 0)
+        };
+
+        let total_size = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|val| val.to_str().ok()?.parse::<u64>().ok())
+            .unwrap_or(0)
+            + downloaded;
+
+        // Set up the progress bar
+        let progress_bar = ProgressBar::new(total_size);
+        progress_bar.set_style(
+            ProgressStyle::with_template("[{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        progress_bar.set_position(downloaded);
+
+        let mut source = response.bytes_stream();
+        while let Some(chunk) = source.next().await {
+            let chunk = chunk?;
+            let bytes_read = chunk.len();
+            if bytes_read == 0 {
+                break;
+            }
+            file.write_all(&chunk[..bytes_read])?;
+            downloaded += bytes_read as u64;
+            progress_bar.set_position(downloaded);
+        }
+        file.sync_all()?;
+
+        progress_bar.finish_with_message("Download complete");
+    }
+
+    if let Some(expected) = expected {
+        if let Err(e) = verify_file(&partial_path, expected) {
+            fs::remove_file(&partial_path)?;
+            return Err(e.context(format!("Downloaded file {} is corrupted", file_name)));
+        }
+    }
+
+    fs::rename(&partial_path, &destination_path)?;
+    Ok(destination_path.to_string_lossy().to_string())
+}
+
+/// Downloads `file_name` into `destination_dir`, verifying it against the checksums in `repo_files`,
+/// which are fetched on first use. A file that is already there is downloaded again if it doesn't match them.
+async fn download_verified_file(
+    endpoint: &str,
+    repo_id: &str,
+    revision: &str,
+    file_name: &str,
+    destination_dir: PathBuf,
+    token: Option<String>,
+    repo_files: &mut Option<HashMap<String, RepoFile>>,
+) -> anyhow::Result<String> {
+    let destination_path = destination_dir.join(file_name);
+    if repo_files.is_none() {
+        match get_repo_files(endpoint, repo_id, revision, token.clone()).await {
+            Ok(files) => *repo_files = Some(files),
+            // without checksums to compare against, the file is used as is
+            Err(e) if destination_path.exists() => {
+                warn!("Could not verify {} of {}: {}", file_name, repo_id, e);
+                return Ok(destination_path.to_string_lossy().to_string());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let expected = repo_files
+        .as_ref()
+        .unwrap()
+        .get(file_name)
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist in {}", file_name, repo_id))?;
+
+    download_file(
+        endpoint,
+        repo_id,
+        revision,
+        file_name,
+        destination_dir,
+        token,
+        Some(expected),
+    )
+    .await
+}
+
+/// Splits a model path in the form of `hf://user/repo[@revision]` into the repo id and the revision, if any.
+pub fn parse_model_path(model_path: &str) -> (String, Option<String>) {
+    let path = model_path.strip_prefix("hf://").unwrap_or(model_path);
+    match path.split_once('@') {
+        Some((repo_id, revision)) if !revision.is_empty() => {
+            (repo_id.to_string(), Some(revision.to_string()))
+        }
+        Some((repo_id, _)) => (repo_id.to_string(), None),
+        None => (path.to_string(), None),
+    }
+}
+
+/// Revisions might be branch names such as `refs/pr/1`, so slashes are escaped in URLs.
+fn encode_revision(revision: &str) -> String {
+    revision.replace('/', "%2F")
+}
+
+/// Returns the directory where the model at `model_path` (`hf://user/repo[@revision]`) is cached.
+///
+/// Models without a revision are cached in `models/<user>/<repo>`,
+/// and pinned revisions in `models/<user>/<repo>/revisions/<revision>`.
+pub fn model_cache_dir(model_path: &str) -> anyhow::Result<PathBuf> {
+    let (repo_id, revision) = parse_model_path(model_path);
+    let invalid_revision = revision.as_ref().is_some_and(|revision| {
+        revision.contains('\\')
+            || revision
+                .split('/')
+                .any(|part| part.is_empty() || part == "." || part == "..")
+    });
+    if !is_valid_repo_id(repo_id.as_str()) || invalid_revision {
         return Err(anyhow::anyhow!(
-            "Failed to download file: {}",
-            response.status()
+            "{} is not a HuggingFace model path. Use hf://<user>/<repo>[@revision]",
+            model_path
         ));
     }
+    let (username, repo_name) = repo_id.split_once('/').unwrap();
 
-    let total_size = response
-        .headers()
-        .get(CONTENT_LENGTH)
-        .and_then(|val| val.to_str().ok()?.parse::<u64>().ok())
-        .unwrap_or(0);
-    let mut file = File::create(&destination_path)?;
+    let repo_dir = home_dir().join("models").join(username).join(repo_name);
+    match revision {
+        Some(revision) => Ok(repo_dir.join("revisions").join(revision.replace('/', "--"))),
+        None => Ok(repo_dir),
+    }
+}
 
-    // Set up the progress bar
-    let progress_bar = ProgressBar::new(total_size);
-    progress_bar.set_style(
-        ProgressStyle::with_template("[{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
+/// Resolves the commit sha that `model_path` (`hf://user/repo[@revision]`) currently points to.
+/// The revision defaults to `main`.
+pub async fn resolve_model_revision(
+    model_path: &str,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    resolve_model_revision_from(hf_endpoint().as_str(), model_path, token).await
+}
 
-    let mut downloaded: u64 = 0;
+async fn resolve_model_revision_from(
+    endpoint: &str,
+    model_path: &str,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    let (repo_id, revision) = parse_model_path(model_path);
+    let revision = revision.unwrap_or_else(|| String::from("main"));
+    let model_info = get_model_info_from(
+        endpoint,
+        repo_id.as_str(),
+        Some(revision.as_str()),
+        false,
+        token,
+    )
+    .await?;
 
-    let mut source = response.bytes_stream();
-    while let Some(Ok(chunk)) = source.next().await {
-        let bytes_read = chunk.len();
-        if bytes_read == 0 {
-            break;
-        }
-        file.write_all(&chunk[..bytes_read])?;
-        downloaded += bytes_read as u64;
-        progress_bar.set_position(downloaded);
+    model_info.sha.ok_or_else(|| {
+        anyhow::anyhow!(
+            "HuggingFace Hub did not report a commit sha for {}",
+            model_path
+        )
+    })
+}
+
+/// Resolves a HuggingFace model from the local cache only, without any network access.
+pub fn resolve_cached_model(model_path: &str, variant: &str) -> anyhow::Result<(String, String)> {
+    let destination_dir = model_cache_dir(model_path)?;
+    if !destination_dir.join(MODEL_METADATA_FILE).exists() {
+        return Err(anyhow::anyhow!(
+            "Model {} is not in the local cache at {}. Download it first without offline mode",
+            model_path,
+            destination_dir.display()
+        ));
     }
 
-    progress_bar.finish_with_message("Download complete");
-    Ok(destination_path.to_string_lossy().to_string())
+    resolve_local_model(destination_dir.to_str().unwrap(), variant)
+        .map_err(|e| anyhow::anyhow!("Cached copy of model {} is not usable: {}", model_path, e))
 }
 
 pub async fn download_model(
@@ -173,65 +514,63 @@ pub async fn download_model(
     variant: String,
     token: Option<String>,
 ) -> anyhow::Result<(String, String)> {
-    let cache_dir = home_dir().join("models");
-    let repo_id = model_path.replace("hf://", "").to_string();
-    let (username, repo_name) = repo_id.split_once("/").ok_or_else(|| {
-        anyhow::anyhow!("This is probabably not a proper HuggingFace path. Check it out")
-    })?;
-    let destination_dir = cache_dir.join(username).join(repo_name);
+    if is_offline() {
+        return resolve_cached_model(model_path.as_str(), variant.as_str());
+    }
+
+    download_model_from(hf_endpoint().as_str(), model_path, variant, token).await
+}
 
-    let config_path = download_file(
+async fn download_model_from(
+    endpoint: &str,
+    model_path: String,
+    variant: String,
+    token: Option<String>,
+) -> anyhow::Result<(String, String)> {
+    let (repo_id, revision) = parse_model_path(model_path.as_str());
+    let revision = revision.unwrap_or_else(|| String::from("main"));
+    let destination_dir = model_cache_dir(model_path.as_str())?;
+
+    let mut repo_files = None;
+    let config_path = download_verified_file(
+        endpoint,
         repo_id.as_str(),
-        "metadata.json",
+        revision.as_str(),
+        MODEL_METADATA_FILE,
         destination_dir.clone(),
         token.clone(),
+        &mut repo_files,
     )
     .await?;
 
-    // Read the metadata.json file
-    let config_content = fs::read_to_string(config_path)?;
-    let config: serde_json::Value = serde_json::from_str(&config_content)?;
-
-    // Parse the "letsearch_version" and "variants"
-    let version = config["letsearch_version"].as_i64().ok_or_else(|| {
-        anyhow::anyhow!("This is probably not a letsearch-compatible model. Check it out")
-    })?;
-    assert_eq!(version, 1);
-
-    let variants = config["variants"]
-        .as_array()
-        .ok_or_else(|| anyhow::anyhow!("This is probably not a letsearch model. check it out"))?;
-
-    // Check if the requested variant exists
-    let variant_info = variants
-        .iter()
-        .find(|v| v["variant"] == variant)
-        .ok_or_else(|| anyhow::anyhow!("Variant not found in config"))?;
+    let metadata = ModelMetadata::from_file(&PathBuf::from(config_path))?;
+    let variant_info = metadata.variant(variant.as_str())?;
 
     // Download the ONNX model for the specified variant
-    let local_model_path = match variant_info["path"].as_str() {
-        Some(model_file) => PathBuf::from(
-            download_file(
-                &repo_id.as_str(),
-                model_file,
-                destination_dir.clone(),
-                token.clone(),
-            )
-            .await?,
-        ),
-        None => unreachable!("unreachable"), // we already varified it's a letsearch model, so there shouldn't be a variant without a path key
-    };
+    let local_model_path = PathBuf::from(
+        download_verified_file(
+            endpoint,
+            repo_id.as_str(),
+            revision.as_str(),
+            variant_info.path.as_str(),
+            destination_dir.clone(),
+            token.clone(),
+            &mut repo_files,
+        )
+        .await?,
+    );
 
-    if let Some(required_files) = config["required_files"].as_array() {
-        for file_name in required_files {
-            download_file(
-                repo_id.as_str(),
-                file_name.as_str().unwrap(),
-                destination_dir.clone(),
-                token.clone(),
-            )
-            .await?;
-        }
+    for file_name in metadata.required_files.iter() {
+        download_verified_file(
+            endpoint,
+            repo_id.as_str(),
+            revision.as_str(),
+            file_name.as_str(),
+            destination_dir.clone(),
+            token.clone(),
+            &mut repo_files,
+        )
+        .await?;
     }
 
     let model_dir = local_model_path
@@ -250,6 +589,106 @@ pub async fn download_model(
     Ok((model_dir, model_file))
 }
 
+/// Outcome of checking a single cached file against the checksums on the Hub.
+pub struct FileCheck {
+    pub file_name: String,
+    pub error: Option<String>,
+}
+
+/// Verifies the files of a cached model against the sizes and checksums reported by the Hub
+/// for the revision it was downloaded from. Files that are not downloaded are skipped.
+pub async fn verify_model(
+    model_path: &str,
+    token: Option<String>,
+) -> anyhow::Result<Vec<FileCheck>> {
+    verify_model_from(hf_endpoint().as_str(), model_path, token).await
+}
+
+async fn verify_model_from(
+    endpoint: &str,
+    model_path: &str,
+    token: Option<String>,
+) -> anyhow::Result<Vec<FileCheck>> {
+    let (repo_id, revision) = parse_model_path(model_path);
+    let revision = revision.unwrap_or_else(|| String::from("main"));
+    let destination_dir = model_cache_dir(model_path)?;
+    if !destination_dir.join(MODEL_METADATA_FILE).exists() {
+        return Err(anyhow::anyhow!(
+            "Model {} is not in the local cache",
+            model_path
+        ));
+    }
+
+    let repo_files = get_repo_files(endpoint, repo_id.as_str(), revision.as_str(), token).await?;
+    let mut file_names: Vec<&String> = repo_files.keys().collect();
+    file_names.sort();
+
+    let checks = file_names
+        .into_iter()
+        .filter(|file_name| destination_dir.join(file_name).exists())
+        .map(|file_name| FileCheck {
+            file_name: file_name.to_string(),
+            error: verify_file(&destination_dir.join(file_name), &repo_files[file_name])
+                .err()
+                .map(|e| e.to_string()),
+        })
+        .collect();
+
+    Ok(checks)
+}
+
+/// Resolves an `hf://datasets/<user>/<repo>/<path>` path passed to the importers.
+///
+/// In offline mode, it is mapped to the local copy under `LETSEARCH_HOME/datasets/<user>/<repo>`.
+/// When `HF_ENDPOINT` points to a mirror, it is rewritten to a download URL on that mirror.
+/// Any other path is returned as is.
+pub fn resolve_dataset_path(path: &str) -> anyhow::Result<String> {
+    let dataset_path = match path.strip_prefix("hf://datasets/") {
+        Some(dataset_path) => dataset_path,
+        None => return Ok(path.to_string()),
+    };
+
+    let parts: Vec<&str> = dataset_path.splitn(3, '/').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.is_empty()) {
+        return Err(anyhow::anyhow!(
+            "{} is not a proper HuggingFace dataset path. Expected hf://datasets/<user>/<repo>/<path>",
+            path
+        ));
+    }
+    let (username, repo_name, file_path) = (parts[0], parts[1], parts[2]);
+
+    if is_offline() {
+        let dataset_dir = home_dir().join("datasets").join(username).join(repo_name);
+        if !dataset_dir.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Dataset {}/{} is not in the local cache at {} and offline mode is enabled",
+                username,
+                repo_name,
+                dataset_dir.display()
+            ));
+        }
+
+        return Ok(dataset_dir.join(file_path).to_string_lossy().to_string());
+    }
+
+    let endpoint = hf_endpoint();
+    if endpoint == DEFAULT_HF_ENDPOINT {
+        return Ok(path.to_string());
+    }
+
+    if file_path.contains(['*', '?', '[']) {
+        return Err(anyhow::anyhow!(
+            "Glob patterns in hf://datasets paths are not supported with a custom HF_ENDPOINT: {}",
+            path
+        ));
+    }
+
+    Ok(format!(
+        "{}/datasets/{}/{}/resolve/main/{}",
+        endpoint, username, repo_name, file_path
+    ))
+}
+
 pub async fn list_models(token: Option<String>) -> anyhow::Result<()> {
     // Create an indefinite spinner progress bar
     let progress_bar = ProgressBar::new_spinner();
@@ -262,7 +701,7 @@ pub async fn list_models(token: Option<String>) -> anyhow::Result<()> {
     progress_bar.enable_steady_tick(Duration::from_millis(100));
     progress_bar.set_message("Listing models...");
 
-    let mut models = get_models("letsearch", token.clone()).await?;
+    let mut models = get_models(hf_endpoint().as_str(), "letsearch", token.clone()).await?;
     if models.is_empty() {
         progress_bar.finish_and_clear();
         println!("No letsearch-compatible models found on HuggingFace Hub :(");
@@ -284,20 +723,596 @@ pub async fn list_models(token: Option<String>) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Parses `hf://datasets/user/repo[@revision]` into the repo ID and the revision.
+pub fn parse_dataset_repo(dataset_path: &str) -> anyhow::Result<(String, Option<String>)> {
+    let path = dataset_path.strip_prefix("hf://datasets/").ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} is not a HuggingFace dataset path. Use hf://datasets/<user>/<repo>",
+            dataset_path
+        )
+    })?;
+    let (repo_id, revision) = parse_model_path(path);
+    if !is_valid_repo_id(repo_id.as_str()) {
+        return Err(anyhow::anyhow!(
+            "{} is not a HuggingFace dataset path. Use hf://datasets/<user>/<repo>",
+            dataset_path
+        ));
+    }
+
+    Ok((repo_id, revision))
+}
+
+/// Whether `repo_id` is a `<user>/<repo>` id of the HuggingFace Hub.
+/// Repo ids are used as paths under `LETSEARCH_HOME`, so `.` and `..` segments are rejected too.
+pub fn is_valid_repo_id(repo_id: &str) -> bool {
+    let parts: Vec<&str> = repo_id.split('/').collect();
+    parts.len() == 2
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && *part != "."
+                && *part != ".."
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+}
+
+fn with_token(request: reqwest::RequestBuilder, token: &str) -> reqwest::RequestBuilder {
+    request.header(
+        AUTHORIZATION,
+        HeaderValue::from_str(format!("BEARER {token}").as_str()).unwrap(),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct LfsAction {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LfsObjectActions {
+    upload: Option<LfsAction>,
+    verify: Option<LfsAction>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LfsBatchObject {
+    oid: String,
+    size: u64,
+    actions: Option<LfsObjectActions>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LfsBatchResponse {
+    objects: Vec<LfsBatchObject>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct CommitResponse {
+    commitUrl: Option<String>,
+    commitOid: Option<String>,
+}
+
+async fn create_dataset_repo_if_missing(
+    client: &reqwest::Client,
+    endpoint: &str,
+    repo_id: &str,
+    private: bool,
+    token: &str,
+) -> anyhow::Result<()> {
+    let url = format!("{}/api/datasets/{}", endpoint, repo_id);
+    let response = with_token(client.get(&url), token).send().await?;
+    if response.status().is_success() {
+        return Ok(());
+    }
+    if response.status() != StatusCode::NOT_FOUND {
+        return Err(anyhow::anyhow!(
+            "Failed to get info of dataset {}: {}",
+            repo_id,
+            response.status()
+        ));
+    }
+
+    let (organization, name) = repo_id.split_once('/').unwrap();
+    let body = serde_json::json!({
+        "type": "dataset",
+        "name": name,
+        "organization": organization,
+        "private": private,
+    });
+    let response = with_token(client.post(format!("{}/api/repos/create", endpoint)), token)
+        .json(&body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Failed to create dataset {}: {}",
+            repo_id,
+            response.status()
+        ));
+    }
+    info!("Created dataset {}", repo_id);
+
+    Ok(())
+}
+
+/// A local file to upload to the LFS storage of a Hub repo.
+#[derive(Clone, Debug)]
+pub struct UploadFile {
+    /// path of the file in the repo
+    pub path: String,
+    pub local_path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Uploads the `files` that are not already on the Hub's LFS storage.
+async fn upload_lfs_files(
+    client: &reqwest::Client,
+    endpoint: &str,
+    repo_id: &str,
+    revision: &str,
+    files: &[UploadFile],
+    token: &str,
+) -> anyhow::Result<()> {
+    let objects: Vec<serde_json::Value> = files
+        .iter()
+        .map(|file| serde_json::json!({"oid": file.sha256, "size": file.size}))
+        .collect();
+    let body = serde_json::json!({
+        "operation": "upload",
+        "transfers": ["basic"],
+        "objects": objects,
+        "hash_algo": "sha256",
+        "ref": {"name": format!("refs/heads/{}", revision)},
+    });
+    let url = format!(
+        "{}/datasets/{}.git/info/lfs/objects/batch",
+        endpoint, repo_id
+    );
+    let response = with_token(client.post(&url), token)
+        .header(ACCEPT, "application/vnd.git-lfs+json")
+        .header(CONTENT_TYPE, "application/vnd.git-lfs+json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "LFS batch request failed: {}",
+            response.status()
+        ));
+    }
+    let batch: LfsBatchResponse = response.json().await?;
+
+    for object in batch.objects {
+        // objects without actions are already stored on the Hub
+        let actions = match object.actions {
+            Some(actions) => actions,
+            None => continue,
+        };
+        let file = files
+            .iter()
+            .find(|file| file.sha256 == object.oid)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected LFS object {} in response", object.oid))?;
+
+        if let Some(upload) = actions.upload {
+            info!("Uploading {} ({} bytes)", file.path, file.size);
+            let mut request = client
+                .put(&upload.href)
+                .header(CONTENT_LENGTH, file.size)
+                .body(tokio::fs::File::open(&file.local_path).await?);
+            for (name, value) in upload.header.iter() {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Failed to upload {}: {}",
+                    file.path,
+                    response.status()
+                ));
+            }
+        }
+
+        if let Some(verify) = actions.verify {
+            let mut request = with_token(client.post(&verify.href), token)
+                .header(CONTENT_TYPE, "application/vnd.git-lfs+json")
+                .body(serde_json::json!({"oid": object.oid, "size": object.size}).to_string());
+            for (name, value) in verify.header.iter() {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Failed to verify the upload of {}: {}",
+                    file.path,
+                    response.status()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Commits `inline_files`, given as paths and contents, and `lfs_files` to the dataset at
+/// `dataset_path` (`hf://datasets/user/repo[@revision]`) in a single commit with the message `summary`.
+/// The dataset is created if it does not exist.
+/// Returns the URL of the commit.
+pub async fn commit_dataset_files(
+    endpoint: &str,
+    dataset_path: &str,
+    inline_files: &[(String, Vec<u8>)],
+    lfs_files: &[UploadFile],
+    summary: &str,
+    private: bool,
+    token: &str,
+) -> anyhow::Result<String> {
+    let (repo_id, revision) = parse_dataset_repo(dataset_path)?;
+    let revision = revision.unwrap_or_else(|| String::from("main"));
+    let client = reqwest::Client::builder().build()?;
+
+    create_dataset_repo_if_missing(&client, endpoint, repo_id.as_str(), private, token).await?;
+    upload_lfs_files(
+        &client,
+        endpoint,
+        repo_id.as_str(),
+        revision.as_str(),
+        lfs_files,
+        token,
+    )
+    .await?;
+
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let mut lines = vec![serde_json::json!({
+        "key": "header",
+        "value": {"summary": summary, "description": ""},
+    })];
+    for (path, content) in inline_files {
+        lines.push(serde_json::json!({
+            "key": "file",
+            "value": {
+                "path": path,
+                "content": base64.encode(content),
+                "encoding": "base64",
+            },
+        }));
+    }
+    for file in lfs_files {
+        lines.push(serde_json::json!({
+            "key": "lfsFile",
+            "value": {"path": file.path, "algo": "sha256", "oid": file.sha256, "size": file.size},
+        }));
+    }
+    let body = lines
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let url = format!(
+        "{}/api/datasets/{}/commit/{}",
+        endpoint,
+        repo_id,
+        encode_revision(revision.as_str())
+    );
+    let response = with_token(client.post(&url), token)
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Failed to commit to {}: {}",
+            dataset_path,
+            response.status()
+        ));
+    }
+    let commit: CommitResponse = response.json().await?;
+    let commit_url = commit
+        .commitUrl
+        .or(commit.commitOid)
+        .unwrap_or_else(|| format!("{}/datasets/{}", endpoint, repo_id));
+
+    Ok(commit_url)
+}
+
+/// Downloads `file_name` from the dataset `repo_id` at `revision` into `destination_dir`.
+/// See `download_file` for how partial and existing files are handled.
+pub async fn download_dataset_file(
+    endpoint: &str,
+    repo_id: &str,
+    revision: &str,
+    file_name: &str,
+    destination_dir: PathBuf,
+    token: Option<String>,
+    expected: Option<&RepoFile>,
+) -> anyhow::Result<String> {
+    download_file(
+        endpoint,
+        format!("datasets/{}", repo_id).as_str(),
+        revision,
+        file_name,
+        destination_dir,
+        token,
+        expected,
+    )
+    .await
+}
+
 #[cfg(test)]
-mod tests {
-    use crate::hf_ops::{download_model, get_model_info, list_models};
+pub(crate) mod tests {
+    use crate::hf_ops::parse_dataset_repo;
+    use crate::hf_ops::{
+        download_model, download_model_from, get_model_info, hf_endpoint, list_models,
+        model_cache_dir, parse_model_path, resolve_cached_model, resolve_dataset_path,
+        resolve_model_revision_from, verify_model_from,
+    };
+    use crate::hf_ops::{sha256_file, BlobLfsInfo, RepoFile};
+    use actix_web::http::header::{CONTENT_RANGE, RANGE};
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
     use std::env::temp_dir;
+    use std::fs;
     use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    type RepoFiles = HashMap<String, Vec<u8>>;
+
+    async fn serve_file(req: HttpRequest, files: web::Data<RepoFiles>) -> HttpResponse {
+        let content = match files.get(req.path()) {
+            Some(content) => content,
+            None => return HttpResponse::NotFound().finish(),
+        };
+
+        let range_start = req
+            .headers()
+            .get(RANGE)
+            .and_then(|range| {
+                range
+                    .to_str()
+                    .ok()?
+                    .strip_prefix("bytes=")?
+                    .strip_suffix('-')
+            })
+            .and_then(|start| start.parse::<usize>().ok());
+        match range_start {
+            Some(start) if start < content.len() => HttpResponse::PartialContent()
+                .insert_header((
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, content.len() - 1, content.len()),
+                ))
+                .body(content[start..].to_vec()),
+            Some(_) => HttpResponse::RangeNotSatisfiable().finish(),
+            None => HttpResponse::Ok().body(content.clone()),
+        }
+    }
+
+    /// Starts a local stand-in for the Hub serving `files` by URL path and returns its endpoint.
+    async fn start_hub(files: Vec<(String, Vec<u8>)>) -> String {
+        let files = web::Data::new(files.into_iter().collect::<RepoFiles>());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(files.clone())
+                .default_service(web::to(serve_file))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let endpoint = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+
+        endpoint
+    }
+
+    /// State of a writable stand-in for the Hub: created datasets, LFS blobs by oid
+    /// and committed files by their resolve URL path.
+    #[derive(Default)]
+    struct WritableHub {
+        datasets: Vec<String>,
+        blobs: HashMap<String, Vec<u8>>,
+        files: RepoFiles,
+    }
+
+    async fn writable_hub_handler(
+        req: HttpRequest,
+        body: web::Bytes,
+        hub: web::Data<Mutex<WritableHub>>,
+    ) -> HttpResponse {
+        let path = req.path().to_string();
+        let host = req.connection_info().host().to_string();
+        let mut hub = hub.lock().unwrap();
+        let method = req.method().as_str();
+
+        if method == "GET" && path.starts_with("/api/datasets/") {
+            let repo_id = path.strip_prefix("/api/datasets/").unwrap();
+            return if hub.datasets.iter().any(|d| d == repo_id) {
+                HttpResponse::Ok().json(serde_json::json!({"id": repo_id}))
+            } else {
+                HttpResponse::NotFound().finish()
+            };
+        }
+        if method == "POST" && path == "/api/repos/create" {
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(request["type"], "dataset");
+            let repo_id = format!(
+                "{}/{}",
+                request["organization"].as_str().unwrap(),
+                request["name"].as_str().unwrap()
+            );
+            hub.datasets.push(repo_id);
+            return HttpResponse::Ok().finish();
+        }
+        if method == "POST" && path.ends_with(".git/info/lfs/objects/batch") {
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            if !request["ref"]["name"]
+                .as_str()
+                .is_some_and(|name| name.starts_with("refs/heads/"))
+            {
+                return HttpResponse::BadRequest().finish();
+            }
+            let objects: Vec<serde_json::Value> = request["objects"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|object| {
+                    let oid = object["oid"].as_str().unwrap();
+                    serde_json::json!({
+                        "oid": oid,
+                        "size": object["size"],
+                        "actions": {
+                            "upload": {"href": format!("http://{}/lfs/{}", host, oid)},
+                            "verify": {"href": format!("http://{}/lfs-verify", host)},
+                        },
+                    })
+                })
+                .collect();
+            return HttpResponse::Ok().json(serde_json::json!({"objects": objects}));
+        }
+        if method == "PUT" && path.starts_with("/lfs/") {
+            let oid = path.strip_prefix("/lfs/").unwrap();
+            assert_eq!(format!("{:x}", Sha256::digest(&body)), oid);
+            hub.blobs.insert(oid.to_string(), body.to_vec());
+            return HttpResponse::Ok().finish();
+        }
+        if method == "POST" && path == "/lfs-verify" {
+            return HttpResponse::Ok().finish();
+        }
+        if method == "POST" && path.starts_with("/api/datasets/") && path.contains("/commit/") {
+            let (repo_id, revision) = path
+                .strip_prefix("/api/datasets/")
+                .unwrap()
+                .split_once("/commit/")
+                .unwrap();
+            let (repo_id, revision) = (repo_id.to_string(), revision.to_string());
+            for line in std::str::from_utf8(&body).unwrap().lines() {
+                let operation: serde_json::Value = serde_json::from_str(line).unwrap();
+                let value = &operation["value"];
+                let content = match operation["key"].as_str().unwrap() {
+                    "file" => base64::engine::general_purpose::STANDARD
+                        .decode(value["content"].as_str().unwrap())
+                        .unwrap(),
+                    "lfsFile" => match hub.blobs.get(value["oid"].as_str().unwrap()) {
+                        Some(blob) => blob.clone(),
+                        None => return HttpResponse::BadRequest().finish(),
+                    },
+                    _ => continue,
+                };
+                let file_path = format!(
+                    "/datasets/{}/resolve/{}/{}",
+                    repo_id,
+                    revision,
+                    value["path"].as_str().unwrap()
+                );
+                hub.files.insert(file_path, content);
+            }
+            return HttpResponse::Ok().json(serde_json::json!({
+                "commitOid": "abc123",
+                "commitUrl": format!("http://{}/datasets/{}/commit/abc123", host, repo_id),
+            }));
+        }
+
+        let files = web::Data::new(hub.files.clone());
+        drop(hub);
+        serve_file(req, files).await
+    }
+
+    /// Starts a local stand-in for the Hub that accepts dataset creation, LFS uploads and commits.
+    pub(crate) async fn start_writable_hub() -> String {
+        let hub = web::Data::new(Mutex::new(WritableHub::default()));
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(hub.clone())
+                .app_data(web::PayloadConfig::new(1 << 26))
+                .default_service(web::to(writable_hub_handler))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let endpoint = format!("http://{}", server.addrs()[0]);
+        tokio::spawn(server.run());
+
+        endpoint
+    }
+
+    /// Files of a fake model repo at `revision`
+    /// along with the model info API response listing their checksums.
+    /// `corrupt` makes the Hub report a wrong checksum for the ONNX file.
+    fn test_model_files(repo_id: &str, revision: &str, corrupt: bool) -> Vec<(String, Vec<u8>)> {
+        let metadata = r#"{
+            "letsearch_version": 1,
+            "variants": [{"variant": "f32", "path": "model.onnx"}],
+            "required_files": ["tokenizer.json"]
+        }"#;
+        let files = vec![
+            ("metadata.json", metadata.as_bytes().to_vec()),
+            ("model.onnx", b"not really an onnx model".repeat(100)),
+            ("tokenizer.json", b"{}".to_vec()),
+        ];
+
+        let siblings: Vec<RepoFile> = files
+            .iter()
+            .map(|(name, content)| {
+                let sha256 = if corrupt && *name == "model.onnx" {
+                    "0".repeat(64)
+                } else {
+                    format!("{:x}", Sha256::digest(content))
+                };
+                RepoFile {
+                    rfilename: name.to_string(),
+                    size: Some(content.len() as u64),
+                    blobId: None,
+                    lfs: Some(BlobLfsInfo {
+                        size: Some(content.len() as u64),
+                        sha256: Some(sha256),
+                        pointer_size: None,
+                    }),
+                }
+            })
+            .collect();
+        let model_info = serde_json::json!({
+            "modelId": repo_id,
+            "sha": format!("{}-sha", revision),
+            "private": false,
+            "siblings": siblings,
+        });
+
+        files
+            .into_iter()
+            .map(|(name, content)| {
+                (
+                    format!("/{}/resolve/{}/{}", repo_id, revision, name),
+                    content,
+                )
+            })
+            .chain(std::iter::once((
+                format!("/api/models/{}/revision/{}", repo_id, revision),
+                model_info.to_string().into_bytes(),
+            )))
+            .collect()
+    }
 
     #[tokio::test]
     async fn test_download_file() {
         let tmp_dir = temp_dir().join("letsearch_models");
         let repo_id = "mys/minilm";
         let file_name = "metadata.json";
-        let downloaded_file = super::download_file(repo_id, file_name, tmp_dir, None)
-            .await
-            .unwrap();
+        let downloaded_file = super::download_file(
+            hf_endpoint().as_str(),
+            repo_id,
+            "main",
+            file_name,
+            tmp_dir,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
         assert!(PathBuf::from(downloaded_file).exists());
     }
 
@@ -320,7 +1335,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_models() {
-        let models = super::get_models("letsearch", None).await.unwrap();
+        let models = super::get_models(hf_endpoint().as_str(), "letsearch", None)
+            .await
+            .unwrap();
         assert!(!models.is_empty()); // Assuming there's at least one "letsearch" model
     }
 
@@ -329,4 +1346,314 @@ mod tests {
         // This function primarily prints to stdout, so we'll just check if it completes without error.
         list_models(None).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_download_model_from_endpoint() {
+        let model_path = "hf://letsearch-test/endpoint";
+        let cache_dir = model_cache_dir(model_path).unwrap();
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).unwrap();
+        }
+        assert!(resolve_cached_model(model_path, "f32").is_err());
+
+        let endpoint = start_hub(test_model_files("letsearch-test/endpoint", "main", false)).await;
+        let (model_dir, model_file) = download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(model_file, "model.onnx");
+        assert!(PathBuf::from(&model_dir).join("tokenizer.json").exists());
+
+        // now it can be resolved without network access
+        let (cached_dir, cached_file) = resolve_cached_model(model_path, "f32").unwrap();
+        assert_eq!((cached_dir, cached_file), (model_dir, model_file));
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redownload_corrupted_cached_model() {
+        let model_path = "hf://letsearch-test/stale";
+        let cache_dir = model_cache_dir(model_path).unwrap();
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).unwrap();
+        }
+
+        let files = test_model_files("letsearch-test/stale", "main", false);
+        let model_content = files[1].1.clone();
+        let endpoint = start_hub(files).await;
+        download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // a cached file that no longer matches the Hub is replaced
+        fs::write(cache_dir.join("model.onnx"), &model_content[..1000]).unwrap();
+        let (model_dir, model_file) = download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read(PathBuf::from(model_dir).join(model_file)).unwrap(),
+            model_content
+        );
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_download() {
+        let model_path = "hf://letsearch-test/resume";
+        let cache_dir = model_cache_dir(model_path).unwrap();
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).unwrap();
+        }
+
+        let files = test_model_files("letsearch-test/resume", "main", false);
+        let model_content = files[1].1.clone();
+
+        // leave a partial file behind as if a previous download was interrupted
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::write(cache_dir.join("model.onnx.part"), &model_content[..1000]).unwrap();
+
+        let endpoint = start_hub(files).await;
+        let (model_dir, model_file) = download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let model_path = PathBuf::from(model_dir).join(model_file);
+        assert_eq!(fs::read(&model_path).unwrap(), model_content);
+        assert_eq!(
+            sha256_file(&model_path).unwrap(),
+            format!("{:x}", Sha256::digest(&model_content))
+        );
+        assert!(!cache_dir.join("model.onnx.part").exists());
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redownload_corrupted_file() {
+        let tmp_dir = temp_dir().join("letsearch_redownload");
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir).unwrap();
+        }
+        fs::create_dir_all(&tmp_dir).unwrap();
+
+        let files = test_model_files("letsearch-test/redownload", "main", false);
+        let model_content = files[1].1.clone();
+        let expected = RepoFile {
+            rfilename: String::from("model.onnx"),
+            size: Some(model_content.len() as u64),
+            blobId: None,
+            lfs: Some(BlobLfsInfo {
+                size: Some(model_content.len() as u64),
+                sha256: Some(format!("{:x}", Sha256::digest(&model_content))),
+                pointer_size: None,
+            }),
+        };
+
+        // a complete but corrupted file is not trusted
+        fs::write(tmp_dir.join("model.onnx"), b"corrupted").unwrap();
+        let endpoint = start_hub(files).await;
+        let downloaded_file = super::download_file(
+            endpoint.as_str(),
+            "letsearch-test/redownload",
+            "main",
+            "model.onnx",
+            tmp_dir.clone(),
+            None,
+            Some(&expected),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read(downloaded_file).unwrap(), model_content);
+
+        // nor is a partial file that cannot be verified
+        fs::write(tmp_dir.join("tokenizer.json.part"), b"{}{}").unwrap();
+        let downloaded_file = super::download_file(
+            endpoint.as_str(),
+            "letsearch-test/redownload",
+            "main",
+            "tokenizer.json",
+            tmp_dir.clone(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read(downloaded_file).unwrap(), b"{}");
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_download() {
+        let model_path = "hf://letsearch-test/corrupted";
+        let cache_dir = model_cache_dir(model_path).unwrap();
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).unwrap();
+        }
+
+        let endpoint = start_hub(test_model_files("letsearch-test/corrupted", "main", true)).await;
+        let err = download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Checksum mismatch"));
+        assert!(!cache_dir.join("model.onnx").exists());
+        assert!(!cache_dir.join("model.onnx.part").exists());
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_model() {
+        let model_path = "hf://letsearch-test/verify";
+        let cache_dir = model_cache_dir(model_path).unwrap();
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).unwrap();
+        }
+
+        let endpoint = start_hub(test_model_files("letsearch-test/verify", "main", false)).await;
+        download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let checks = verify_model_from(endpoint.as_str(), model_path, None)
+            .await
+            .unwrap();
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|check| check.error.is_none()));
+
+        // simulate a truncated file left by an older version of letsearch
+        fs::write(cache_dir.join("model.onnx"), b"truncated").unwrap();
+        let checks = verify_model_from(endpoint.as_str(), model_path, None)
+            .await
+            .unwrap();
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|check| check.error.is_some())
+            .map(|check| check.file_name.as_str())
+            .collect();
+        assert_eq!(failed, vec!["model.onnx"]);
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_model_path() {
+        assert_eq!(
+            parse_model_path("hf://mys/minilm"),
+            (String::from("mys/minilm"), None)
+        );
+        assert_eq!(
+            parse_model_path("hf://mys/minilm@v1.0"),
+            (String::from("mys/minilm"), Some(String::from("v1.0")))
+        );
+        assert!(model_cache_dir("hf://mys/minilm@refs/pr/1")
+            .unwrap()
+            .ends_with("mys/minilm/revisions/refs--pr--1"));
+        assert!(model_cache_dir("hf://mys").is_err());
+        assert!(model_cache_dir("hf://../minilm").is_err());
+        assert!(model_cache_dir("hf://mys/..").is_err());
+        assert!(model_cache_dir("hf://mys/minilm@..").is_err());
+        assert!(model_cache_dir("hf://mys/minilm@refs/../..").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_pinned_revision() {
+        let model_path = "hf://letsearch-test/pinned@v2";
+        let repo_dir = model_cache_dir("hf://letsearch-test/pinned").unwrap();
+        if repo_dir.exists() {
+            fs::remove_dir_all(&repo_dir).unwrap();
+        }
+
+        let endpoint = start_hub(test_model_files("letsearch-test/pinned", "v2", false)).await;
+        let sha = resolve_model_revision_from(endpoint.as_str(), model_path, None)
+            .await
+            .unwrap();
+        assert_eq!(sha, "v2-sha");
+
+        let (model_dir, _) = download_model_from(
+            endpoint.as_str(),
+            model_path.to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            PathBuf::from(model_dir),
+            repo_dir.join("revisions").join("v2")
+        );
+
+        // the unpinned revision is not served by the stand-in, so it's not reused from the cache
+        assert!(download_model_from(
+            endpoint.as_str(),
+            "hf://letsearch-test/pinned".to_string(),
+            "f32".to_string(),
+            None,
+        )
+        .await
+        .is_err());
+
+        fs::remove_dir_all(&repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_dataset_path() {
+        assert_eq!(
+            resolve_dataset_path("./docs/*.jsonl").unwrap(),
+            "./docs/*.jsonl"
+        );
+        assert!(resolve_dataset_path("hf://datasets/only-user").is_err());
+    }
+
+    #[test]
+    fn test_parse_dataset_repo() {
+        assert_eq!(
+            parse_dataset_repo("hf://datasets/org/name").unwrap(),
+            (String::from("org/name"), None)
+        );
+        assert_eq!(
+            parse_dataset_repo("hf://datasets/org/name@v1").unwrap(),
+            (String::from("org/name"), Some(String::from("v1")))
+        );
+        assert!(parse_dataset_repo("hf://org/name").is_err());
+        assert!(parse_dataset_repo("hf://datasets/name").is_err());
+        assert!(parse_dataset_repo("hf://datasets/org/name/file.jsonl").is_err());
+        assert!(parse_dataset_repo("hf://datasets/../name").is_err());
+        assert!(parse_dataset_repo("hf://datasets/org/..").is_err());
+        assert!(parse_dataset_repo("hf://datasets/org/.").is_err());
+        assert!(parse_dataset_repo("hf://datasets/org/na\\me").is_err());
+        assert!(parse_dataset_repo("hf://datasets/org/na me").is_err());
+        assert!(parse_dataset_repo("hf://datasets/my-org/name_v1.2").is_ok());
+    }
 }
@@ -1,4 +1,8 @@
+pub mod auth;
 pub mod collection;
+pub mod grpc;
 pub mod hf_ops;
+pub mod mcp;
+pub mod metrics;
 pub mod model;
 pub mod serve;
@@ -1,10 +1,24 @@
 use anyhow;
 use chrono;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use env_logger::fmt::Formatter;
+use letsearch::auth::ApiKeys;
+use letsearch::collection::collection_archive::{
+    pack_collection, pull_collection, push_collection, unpack_collection,
+};
 use letsearch::collection::collection_manager::CollectionManager;
-use letsearch::collection::collection_utils::CollectionConfig;
-use letsearch::hf_ops::list_models;
+use letsearch::collection::collection_type::Collection;
+use letsearch::collection::collection_utils::{
+    format_size, home_dir, list_collection_configs, CollectionConfig, CollectionStats,
+    ExportContent, ExportFormat, FusionMethod, ListMode, MmrOptions, SearchColumn, SearchOptions,
+    VirtualField,
+};
+use letsearch::collection::migrations::{migrate_collection, pending_collection_migrations};
+use letsearch::hf_ops::{download_model, list_models, set_offline, verify_model};
+use letsearch::mcp::{run_mcp_http, run_mcp_stdio};
+use letsearch::model::model_cache::{
+    collections_using_model, get_cached_model, list_cached_models, remove_cached_model, CachedModel,
+};
 use letsearch::serve::run_server;
 use log::{info, Record};
 use std::io::Write;
@@ -22,6 +36,11 @@ use std::io::Write;
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// resolve models and datasets only from the local cache in LETSEARCH_HOME
+    /// without accessing the network. Same as setting LETSEARCH_OFFLINE=1
+    #[arg(long, global = true, action = clap::ArgAction::SetTrue)]
+    offline: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -40,7 +59,10 @@ pub enum Commands {
         collection_name: String,
 
         /// Model to create embeddings.
-        /// You can also give a hf:// path and it will be automatically  downloaded.
+        /// You can also give a hf:// path and it will be automatically  downloaded,
+        /// or a local directory (plain or file:// path) with a metadata.json file.
+        /// Use hf://user/repo@revision to pin a branch, tag or commit.
+        /// The resolved commit is recorded in the collection config.
         #[arg(short, long, default_value = "hf://mys/minilm")]
         model: String,
 
@@ -62,11 +84,60 @@ pub enum Commands {
         #[arg(short, long, action = clap::ArgAction::Append)]
         index_columns: Vec<String>,
 
+        /// how to embed list columns: join the elements with newlines into a single text,
+        /// or embed each element separately under the key of its row
+        #[arg(long, value_enum, default_value_t = ListMode::Join)]
+        list_mode: ListMode,
+
+        /// virtual field to embed built from a template over columns, as NAME=TEMPLATE,
+        /// e.g. "title_body={title}\n{body}". Add NAME to --index-columns to embed it.
+        /// You can provide this option multiple times
+        #[arg(long, action = clap::ArgAction::Append)]
+        template_field: Vec<String>,
+
+        /// virtual field to embed built from a SQL expression over columns, as NAME=EXPR,
+        /// e.g. "summary=title || ': ' || abstract". Add NAME to --index-columns to embed it.
+        /// You can provide this option multiple times
+        #[arg(long, action = clap::ArgAction::Append)]
+        sql_field: Vec<String>,
+
         /// remove and re-create collection if it exists
         #[arg(long, action=clap::ArgAction::SetTrue)]
         overwrite: bool,
     },
 
+    /// serve collections to AI agents over the Model Context Protocol
+    Mcp {
+        /// collections to serve. You can provide this option multiple times.
+        /// All collections are served if omitted
+        #[arg(short, long = "collection-name", action = clap::ArgAction::Append)]
+        collection_names: Vec<String>,
+
+        /// serve over streamable HTTP at /mcp instead of stdio
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        http: bool,
+
+        /// host to listen to with --http
+        #[arg(short('H'), long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// port to listen to with --http
+        #[arg(short, long, default_value = "7899")]
+        port: i32,
+
+        /// maximum number of results the search tool can return
+        #[arg(long, default_value = "100")]
+        max_limit: u32,
+
+        /// JSON file of API keys to require with --http, in the same format as for `serve`
+        #[arg(long, requires = "http")]
+        api_keys: Option<std::path::PathBuf>,
+
+        /// HuggingFace token. Only needed when you want to access private repos
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
     /// serve a collection for search over web API
     Serve {
         /// collection to serve
@@ -81,6 +152,26 @@ pub enum Commands {
         #[arg(short, long, default_value = "7898")]
         port: i32,
 
+        /// maximum number of results a search request can ask for
+        #[arg(long, default_value = "100")]
+        max_limit: u32,
+
+        /// also serve the gRPC API on this port
+        #[arg(long)]
+        grpc_port: Option<i32>,
+
+        /// JSON file of API keys to require, with the collections and role of each key
+        #[arg(long)]
+        api_keys: Option<std::path::PathBuf>,
+
+        /// reload the collection when its files change, e.g., after re-indexing it
+        #[arg(long)]
+        watch: bool,
+
+        /// seconds between checks for changes with --watch
+        #[arg(long, default_value = "5")]
+        watch_interval: u64,
+
         /// HuggingFace token. Only needed when you want to access private repos
         #[arg(long)]
         hf_token: Option<String>,
@@ -92,6 +183,458 @@ pub enum Commands {
         #[arg(long)]
         hf_token: Option<String>,
     },
+
+    /// search a collection from the command line without running a server
+    Search {
+        /// collection to search
+        #[arg(short, long, required = true)]
+        collection_name: String,
+
+        /// indexed column to search in. You can provide this option multiple times
+        /// to search several columns, and give a column a weight with NAME^WEIGHT, e.g. title^2
+        #[arg(long = "column", required = true, action = clap::ArgAction::Append)]
+        columns: Vec<String>,
+
+        /// how results from several columns are merged
+        #[arg(long, value_enum, default_value_t = FusionMethod::Max)]
+        fusion: FusionMethod,
+
+        /// diversify results with maximal marginal relevance. Between 0 (most diverse) and 1 (most relevant)
+        #[arg(long)]
+        mmr_lambda: Option<f32>,
+
+        /// number of candidates to re-rank with MMR. Defaults to 4 times the limit
+        #[arg(long, requires = "mmr_lambda")]
+        mmr_pool_size: Option<u32>,
+
+        /// keep only the best hit among those with the same value in this column, e.g. document_id
+        #[arg(long)]
+        collapse_column: Option<String>,
+
+        /// drop hits scoring lower than this
+        #[arg(long)]
+        min_score: Option<f32>,
+
+        /// number of hits to skip, e.g. to get the second page of results
+        #[arg(long, default_value = "0")]
+        offset: u32,
+
+        /// keep only hits whose column is equal to a value, as COLUMN=VALUE.
+        /// VALUE is parsed as JSON if possible, so that numbers and lists of values can be given.
+        /// You can provide this option multiple times
+        #[arg(long = "filter", action = clap::ArgAction::Append)]
+        filters: Vec<String>,
+
+        /// query text. If omitted or "-", one query per line is read from stdin
+        query: Option<String>,
+
+        /// read one query per line from this file
+        #[arg(short('f'), long)]
+        queries_file: Option<String>,
+
+        /// number of results per query
+        #[arg(short, long, default_value = "10")]
+        limit: u32,
+
+        /// additional columns to return with each result.
+        /// You can provide this option multiple times
+        #[arg(short, long = "return-column", action = clap::ArgAction::Append)]
+        return_columns: Vec<String>,
+
+        /// output format
+        #[arg(short('o'), long, value_enum, default_value = "table")]
+        format: OutputFormat,
+
+        /// HuggingFace token. Only needed when you want to access private repos
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
+    /// show statistics of a collection
+    Info {
+        /// collection to inspect
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// print statistics as JSON
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+
+    /// delete a collection from disk
+    #[command(visible_alias = "drop")]
+    Delete {
+        /// collection to delete
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// delete the collection even if it's being served
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        force: bool,
+    },
+
+    /// rename a collection
+    Rename {
+        /// collection to rename
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// new name of the collection
+        #[arg(required = true)]
+        new_name: String,
+
+        /// rename the collection even if it's being served
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        force: bool,
+    },
+
+    /// copy a collection to a new collection, e.g., to experiment with it
+    Copy {
+        /// collection to copy
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// name of the new collection
+        #[arg(required = true)]
+        new_name: String,
+
+        /// copy the collection even if it's being served
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        force: bool,
+    },
+
+    /// export the data and vectors of a collection to a Parquet or JSONL file
+    Export {
+        /// collection to export
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// output file, e.g., export.parquet or export.jsonl
+        #[arg(required = true)]
+        output: String,
+
+        /// output format. Inferred from the output file extension if not given
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+
+        /// export only the columns of the table without vectors
+        #[arg(long, action=clap::ArgAction::SetTrue, conflicts_with = "vectors_only")]
+        metadata_only: bool,
+
+        /// export only `_key` and the vectors of indexed columns
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        vectors_only: bool,
+    },
+
+    /// bundle a collection into a single archive with a checksummed manifest
+    Pack {
+        /// collection to pack
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// archive file to write. Defaults to <collection_name>.tar.gz
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// restore a collection from an archive created with `pack`
+    Unpack {
+        /// archive file to restore
+        #[arg(required = true)]
+        archive: String,
+
+        /// replace the collection if it already exists
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        overwrite: bool,
+    },
+
+    /// upload a collection to a dataset on HuggingFace Hub
+    Push {
+        /// collection to upload
+        #[arg(required = true)]
+        collection_name: String,
+
+        /// dataset to upload to, e.g., hf://datasets/<user>/<repo>[@revision]
+        #[arg(required = true)]
+        dataset: String,
+
+        /// create the dataset as private if it does not exist
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        private: bool,
+
+        /// HuggingFace token with write access
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
+    /// download a collection uploaded with `push` from a dataset on HuggingFace Hub
+    Pull {
+        /// dataset to download from, e.g., hf://datasets/<user>/<repo>[@revision]
+        #[arg(required = true)]
+        dataset: String,
+
+        /// replace the collection if it already exists
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        overwrite: bool,
+
+        /// HuggingFace token. Only needed when you want to access private repos
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
+    /// upgrade collections written by an older version of letsearch to the current on-disk layout
+    Migrate {
+        /// collection to migrate
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        collection_name: Option<String>,
+
+        /// migrate all the collections in LETSEARCH_HOME
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        all: bool,
+
+        /// only print the pending migrations
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        dry_run: bool,
+    },
+
+    /// manage models in the local cache
+    Models {
+        #[command(subcommand)]
+        command: ModelsCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModelsCommands {
+    /// list cached models with their variants and disk sizes
+    List,
+
+    /// show details of a cached model from its metadata.json
+    Show {
+        /// model path, e.g., hf://mys/minilm or hf://mys/minilm@<revision>
+        #[arg(required = true)]
+        model: String,
+    },
+
+    /// download a model variant to the cache without creating a collection
+    Fetch {
+        /// model path, e.g., hf://mys/minilm or hf://mys/minilm@<revision>
+        #[arg(required = true)]
+        model: String,
+
+        /// model variant to download
+        #[arg(short, long, default_value = "f32")]
+        variant: String,
+
+        /// HuggingFace token. Only needed when you want to access private repos
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
+    /// verify cached files against the sizes and checksums on HuggingFace Hub
+    Verify {
+        /// model path, e.g., hf://mys/minilm or hf://mys/minilm@<revision>
+        #[arg(required = true)]
+        model: String,
+
+        /// HuggingFace token. Only needed when you want to access private repos
+        #[arg(long)]
+        hf_token: Option<String>,
+    },
+
+    /// remove a model from the cache.
+    /// Removing hf://user/repo also removes all of its cached revisions
+    Remove {
+        /// model path, e.g., hf://mys/minilm or hf://mys/minilm@<revision>
+        #[arg(required = true)]
+        model: String,
+
+        /// remove the model even if it's used by a collection
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        force: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum OutputFormat {
+    /// human-readable table
+    Table,
+    /// a single JSON array with an object per query
+    Json,
+    /// a JSON object per query on each line
+    Jsonl,
+}
+
+/// Token given on the command line, falling back to the `HF_TOKEN` environment variable
+fn hf_token_or_env(hf_token: &Option<String>) -> Option<String> {
+    hf_token.clone().or_else(|| std::env::var("HF_TOKEN").ok())
+}
+
+/// Splits an option given as NAME=VALUE, such as a virtual field definition or a filter.
+fn split_field_definition(definition: &str) -> anyhow::Result<(String, String)> {
+    match definition.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() && !value.is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(anyhow::anyhow!(
+            "Invalid option value '{}'. Expected NAME=VALUE",
+            definition
+        )),
+    }
+}
+
+/// Parses a column to search in given as NAME or NAME^WEIGHT.
+fn parse_search_column(column: &str) -> anyhow::Result<SearchColumn> {
+    match column.rsplit_once('^') {
+        Some((name, weight)) => Ok(SearchColumn {
+            column_name: name.to_string(),
+            weight: weight
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid weight in column '{}'", column))?,
+        }),
+        None => Ok(SearchColumn::new(column)),
+    }
+}
+
+/// Queries from the argument, a file or stdin, skipping empty lines.
+fn read_queries(
+    query: &Option<String>,
+    queries_file: &Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    let lines: Vec<String> = match (query.as_deref(), queries_file) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "Provide either a query or --queries-file, not both"
+            ))
+        }
+        (Some(query), None) if query != "-" => vec![query.to_string()],
+        (_, Some(queries_file)) => std::fs::read_to_string(queries_file)?
+            .lines()
+            .map(|line| line.to_string())
+            .collect(),
+        _ => std::io::stdin().lines().collect::<Result<_, _>>()?,
+    };
+
+    Ok(lines
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let text = text.replace(['\n', '\r', '\t'], " ");
+    if text.chars().count() > max_chars {
+        format!(
+            "{}...",
+            text.chars().take(max_chars - 3).collect::<String>()
+        )
+    } else {
+        text
+    }
+}
+
+fn print_search_table(query: &str, hits: &[serde_json::Value], return_columns: &[String]) {
+    println!("query: {}", query);
+    let mut header = format!(
+        "{:>4}  {:>7}  {:>8}  {:<60}",
+        "#", "SCORE", "KEY", "CONTENT"
+    );
+    for column in return_columns {
+        header.push_str(format!("  {:<20}", truncate(column, 20)).as_str());
+    }
+    println!("{}", header.trim_end());
+
+    for (rank, hit) in hits.iter().enumerate() {
+        let mut line = format!(
+            "{:>4}  {:>7.4}  {:>8}  {:<60}",
+            rank + 1,
+            hit["score"].as_f64().unwrap_or_default(),
+            hit["key"],
+            truncate(hit["content"].as_str().unwrap_or_default(), 60)
+        );
+        for column in return_columns {
+            let value = match &hit[column.as_str()] {
+                serde_json::Value::String(value) => value.to_string(),
+                value => value.to_string(),
+            };
+            line.push_str(format!("  {:<20}", truncate(value.as_str(), 20)).as_str());
+        }
+        println!("{}", line.trim_end());
+    }
+    println!();
+}
+
+fn print_collection_stats(stats: &CollectionStats) {
+    println!("collection:            {}", stats.name);
+    println!("rows:                  {}", stats.row_count);
+    println!(
+        "model:                 {} ({})",
+        stats.model_name, stats.model_variant
+    );
+    if let Some(revision) = &stats.model_revision {
+        println!("model revision:        {}", revision);
+    }
+    println!("serialization version: {}", stats.serialization_version);
+    println!(
+        "disk size:             {} (db: {}, indexes: {})",
+        format_size(stats.total_size),
+        format_size(stats.db_size),
+        format_size(stats.index_size)
+    );
+
+    println!("schema:");
+    for column in stats.schema.iter() {
+        println!("     {:<30} {}", column.name, column.data_type);
+    }
+
+    println!("vector indexes:");
+    if stats.indexes.is_empty() {
+        println!("     (none)");
+    }
+    for column in stats.indexes.iter() {
+        let index = &column.index;
+        println!(
+            "     {:<30} {} vectors, {} dims, capacity {}, metric {}, scalar {}, {} on disk",
+            column.column,
+            index.size,
+            index.dimensions,
+            index.capacity,
+            index.metric,
+            index.scalar_kind,
+            format_size(index.disk_size)
+        );
+    }
+}
+
+fn print_cached_model(model: &CachedModel) {
+    println!("model:           {}", model.model_path);
+    println!("path:            {}", model.dir.display());
+    println!("size:            {}", format_size(model.size));
+    println!("metadata format: v{}", model.metadata.letsearch_version);
+    println!("variants:");
+    for variant in model.metadata.variants.iter() {
+        let status = if model
+            .downloaded_variants()
+            .contains(&variant.variant.as_str())
+        {
+            "downloaded"
+        } else {
+            "not downloaded"
+        };
+        println!("     {:<6} {} ({})", variant.variant, variant.path, status);
+    }
+    if model.metadata.variants.is_empty() {
+        println!("     (none)");
+    }
+    println!(
+        "required files:  {}",
+        model.metadata.required_files.join(", ")
+    );
+    let missing_files = model.missing_files();
+    if !missing_files.is_empty() {
+        println!("missing files:   {}", missing_files.join(", "));
+    }
 }
 
 #[tokio::main]
@@ -111,6 +654,9 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let cli = Cli::parse(); // Automatically parses the arguments into the struct
+    if cli.offline {
+        set_offline(true);
+    }
 
     match &cli.command {
         Commands::Index {
@@ -121,9 +667,32 @@ async fn main() -> anyhow::Result<()> {
             hf_token,
             batch_size,
             index_columns,
+            list_mode,
+            template_field,
+            sql_field,
             overwrite,
         } => {
             let mut config = CollectionConfig::default();
+            config.list_mode = *list_mode;
+            for field in template_field {
+                let (name, template) = split_field_definition(field)?;
+                config.virtual_fields.push(VirtualField {
+                    name,
+                    template: Some(template.replace("\\n", "\n")),
+                    sql: None,
+                });
+            }
+            for field in sql_field {
+                let (name, sql) = split_field_definition(field)?;
+                config.virtual_fields.push(VirtualField {
+                    name,
+                    template: None,
+                    sql: Some(sql),
+                });
+            }
+            for field in &config.virtual_fields {
+                field.validate()?;
+            }
             config.name = collection_name.to_string();
             config.index_columns = index_columns.to_vec();
             config.model_name = model.to_string();
@@ -170,8 +739,23 @@ async fn main() -> anyhow::Result<()> {
             collection_name,
             host,
             port,
+            max_limit,
+            grpc_port,
+            api_keys,
+            watch,
+            watch_interval,
             hf_token,
         } => {
+            if *max_limit < 1 {
+                return Err(anyhow::anyhow!("--max-limit should be at least 1"));
+            }
+            if *watch_interval < 1 {
+                return Err(anyhow::anyhow!("--watch-interval should be at least 1"));
+            }
+            let api_keys = match api_keys {
+                Some(path) => Some(ApiKeys::from_file(path)?),
+                None => None,
+            };
             let token = if let Some(token) = hf_token {
                 Some(token.to_string())
             } else {
@@ -187,10 +771,63 @@ async fn main() -> anyhow::Result<()> {
                 port.to_owned(),
                 collection_name.to_string(),
                 token,
+                max_limit.to_owned(),
+                grpc_port.to_owned(),
+                api_keys,
+                watch.then(|| std::time::Duration::from_secs(*watch_interval)),
             )
             .await?;
         }
 
+        Commands::Mcp {
+            collection_names,
+            http,
+            host,
+            port,
+            max_limit,
+            api_keys,
+            hf_token,
+        } => {
+            if *max_limit < 1 {
+                return Err(anyhow::anyhow!("--max-limit should be at least 1"));
+            }
+            let api_keys = match api_keys {
+                Some(path) => Some(ApiKeys::from_file(path)?),
+                None => None,
+            };
+            let collection_names = if collection_names.is_empty() {
+                list_collection_configs()?
+                    .into_iter()
+                    .map(|config| config.name)
+                    .collect()
+            } else {
+                collection_names.to_vec()
+            };
+            if collection_names.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No collections to serve. Create one with `letsearch index` first"
+                ));
+            }
+
+            let collection_manager = CollectionManager::new(hf_token_or_env(hf_token));
+            for collection_name in collection_names {
+                collection_manager.load_collection(collection_name).await?;
+            }
+
+            if *http {
+                run_mcp_http(
+                    host.to_string(),
+                    port.to_owned(),
+                    collection_manager,
+                    max_limit.to_owned(),
+                    api_keys,
+                )
+                .await?;
+            } else {
+                run_mcp_stdio(collection_manager, max_limit.to_owned()).await?;
+            }
+        }
+
         Commands::ListModels { hf_token } => {
             let token = if let Some(token) = hf_token {
                 Some(token.to_string())
@@ -203,6 +840,324 @@ async fn main() -> anyhow::Result<()> {
             };
             list_models(token).await?;
         }
+
+        Commands::Search {
+            collection_name,
+            columns,
+            fusion,
+            mmr_lambda,
+            mmr_pool_size,
+            collapse_column,
+            min_score,
+            offset,
+            filters,
+            query,
+            queries_file,
+            limit,
+            return_columns,
+            format,
+            hf_token,
+        } => {
+            let options = SearchOptions {
+                columns: columns
+                    .iter()
+                    .map(|column| parse_search_column(column))
+                    .collect::<anyhow::Result<_>>()?,
+                limit: limit.to_owned(),
+                fusion: *fusion,
+                mmr: mmr_lambda.map(|lambda| MmrOptions {
+                    lambda,
+                    pool_size: mmr_pool_size.to_owned(),
+                }),
+                collapse_column: collapse_column.clone(),
+                min_score: min_score.to_owned(),
+                offset: offset.to_owned(),
+                filters: filters
+                    .iter()
+                    .map(|filter| {
+                        let (column, value) = split_field_definition(filter)?;
+                        let value = serde_json::from_str(&value)
+                            .unwrap_or(serde_json::Value::String(value));
+                        Ok((column, value))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            };
+            options.validate()?;
+            let queries = read_queries(query, queries_file)?;
+
+            let collection_manager = CollectionManager::new(hf_token_or_env(hf_token));
+            collection_manager
+                .load_collection(collection_name.to_string())
+                .await?;
+
+            let mut outputs = Vec::new();
+            for query in queries {
+                let results = collection_manager
+                    .search(collection_name.to_string(), query.clone(), options.clone())
+                    .await?;
+                let keys: Vec<u64> = results.iter().map(|result| result.key).collect();
+                let rows = collection_manager
+                    .get_rows(collection_name, &keys, return_columns)
+                    .await?;
+
+                let hits: Vec<serde_json::Value> = results
+                    .iter()
+                    .map(|result| {
+                        let mut hit = serde_json::json!({
+                            "key": result.key,
+                            "score": result.score,
+                            "content": result.content,
+                            "matched_columns": result.matched_columns,
+                        });
+                        if let Some(fields) = rows.get(&result.key) {
+                            hit.as_object_mut().unwrap().extend(fields.clone());
+                        }
+                        hit
+                    })
+                    .collect();
+
+                match format {
+                    OutputFormat::Table => print_search_table(&query, &hits, return_columns),
+                    OutputFormat::Jsonl => {
+                        println!("{}", serde_json::json!({"query": query, "results": hits}))
+                    }
+                    OutputFormat::Json => {
+                        outputs.push(serde_json::json!({"query": query, "results": hits}))
+                    }
+                }
+            }
+
+            if let OutputFormat::Json = format {
+                println!("{}", serde_json::to_string_pretty(&outputs)?);
+            }
+        }
+
+        Commands::Info {
+            collection_name,
+            json,
+        } => {
+            // no need to load the model just to inspect the collection
+            let collection = Collection::from(collection_name.to_string()).await?;
+            let stats = collection.stats().await?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print_collection_stats(&stats);
+            }
+        }
+
+        Commands::Delete {
+            collection_name,
+            force,
+        } => {
+            let collection_manager = CollectionManager::new(None);
+            collection_manager
+                .delete_collection(collection_name, force.to_owned())
+                .await?;
+        }
+
+        Commands::Rename {
+            collection_name,
+            new_name,
+            force,
+        } => {
+            let collection_manager = CollectionManager::new(None);
+            collection_manager
+                .rename_collection(collection_name, new_name, force.to_owned())
+                .await?;
+        }
+
+        Commands::Copy {
+            collection_name,
+            new_name,
+            force,
+        } => {
+            let collection_manager = CollectionManager::new(None);
+            collection_manager
+                .copy_collection(collection_name, new_name, force.to_owned())
+                .await?;
+        }
+
+        Commands::Export {
+            collection_name,
+            output,
+            format,
+            metadata_only,
+            vectors_only,
+        } => {
+            let format = match format {
+                Some(format) => *format,
+                None => ExportFormat::from_path(output)?,
+            };
+            let content = if *metadata_only {
+                ExportContent::MetadataOnly
+            } else if *vectors_only {
+                ExportContent::VectorsOnly
+            } else {
+                ExportContent::All
+            };
+
+            // vectors are read from the index files, so the model is not needed
+            let collection = Collection::from(collection_name.to_string()).await?;
+            collection.export(output, format, content).await?;
+        }
+
+        Commands::Pack {
+            collection_name,
+            output,
+        } => {
+            let output = match output {
+                Some(output) => output.to_string(),
+                None => format!("{}.tar.gz", collection_name),
+            };
+            pack_collection(collection_name, std::path::Path::new(output.as_str()))?;
+        }
+
+        Commands::Unpack { archive, overwrite } => {
+            unpack_collection(std::path::Path::new(archive), overwrite.to_owned()).await?;
+        }
+
+        Commands::Push {
+            collection_name,
+            dataset,
+            private,
+            hf_token,
+        } => {
+            push_collection(
+                collection_name,
+                dataset,
+                private.to_owned(),
+                hf_token_or_env(hf_token),
+            )
+            .await?;
+        }
+
+        Commands::Pull {
+            dataset,
+            overwrite,
+            hf_token,
+        } => {
+            pull_collection(dataset, overwrite.to_owned(), hf_token_or_env(hf_token)).await?;
+        }
+
+        Commands::Migrate {
+            collection_name,
+            all,
+            dry_run,
+        } => {
+            let names = match collection_name {
+                Some(name) if !*all => vec![name.to_string()],
+                _ => list_collection_configs()?
+                    .into_iter()
+                    .map(|config| config.name)
+                    .collect(),
+            };
+
+            for name in names {
+                if *dry_run {
+                    let pending = pending_collection_migrations(name.as_str())?;
+                    if pending.is_empty() {
+                        println!("{}: up to date", name);
+                    }
+                    for description in pending {
+                        println!("{}: {}", name, description);
+                    }
+                    continue;
+                }
+
+                let report = migrate_collection(name.as_str())?;
+                match report.backup_dir {
+                    Some(backup_dir) => println!(
+                        "{}: migrated from version {} to {}, backup at {}",
+                        name,
+                        report.from_version,
+                        report.to_version,
+                        backup_dir.display()
+                    ),
+                    None => println!("{}: up to date", name),
+                }
+            }
+        }
+
+        Commands::Models { command } => match command {
+            ModelsCommands::List => {
+                let models = list_cached_models()?;
+                if models.is_empty() {
+                    println!(
+                        "No models in the cache at {}",
+                        home_dir().join("models").display()
+                    );
+                    return Ok(());
+                }
+
+                println!("{:<50} {:<16} {:>10}", "MODEL", "VARIANTS", "SIZE");
+                for model in models {
+                    println!(
+                        "{:<50} {:<16} {:>10}",
+                        model.model_path,
+                        model.downloaded_variants().join(","),
+                        format_size(model.size)
+                    );
+                }
+            }
+
+            ModelsCommands::Show { model } => {
+                let model = get_cached_model(model)?;
+                print_cached_model(&model);
+                let collections = collections_using_model(model.model_path.as_str())?;
+                if !collections.is_empty() {
+                    println!("used by:         {}", collections.join(", "));
+                }
+            }
+
+            ModelsCommands::Fetch {
+                model,
+                variant,
+                hf_token,
+            } => {
+                if !model.starts_with("hf://") {
+                    return Err(anyhow::anyhow!(
+                        "Only hf:// models can be fetched to the cache"
+                    ));
+                }
+                let (model_dir, model_file) = download_model(
+                    model.to_string(),
+                    variant.to_string(),
+                    hf_token_or_env(hf_token),
+                )
+                .await?;
+                info!(
+                    "Model {} ({}) is ready at {}/{}",
+                    model, variant, model_dir, model_file
+                );
+            }
+
+            ModelsCommands::Verify { model, hf_token } => {
+                let checks = verify_model(model, hf_token_or_env(hf_token)).await?;
+                let mut failed = 0;
+                for check in checks.iter() {
+                    match &check.error {
+                        Some(error) => {
+                            failed += 1;
+                            println!("FAILED {}: {}", check.file_name, error);
+                        }
+                        None => println!("OK     {}", check.file_name),
+                    }
+                }
+
+                if failed > 0 {
+                    return Err(anyhow::anyhow!(
+                        "{} of {} file(s) failed verification. Remove the model with `letsearch models remove` and fetch it again",
+                        failed,
+                        checks.len()
+                    ));
+                }
+            }
+
+            ModelsCommands::Remove { model, force } => {
+                remove_cached_model(model, force.to_owned())?;
+            }
+        },
     }
 
     Ok(())
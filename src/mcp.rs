@@ -0,0 +1,605 @@
+use crate::auth::{ApiKey, ApiKeys};
+use crate::collection::collection_manager::CollectionManager;
+use crate::collection::collection_utils::{SearchColumn, SearchOptions, ServerLock};
+use actix_web::http::header;
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::RwLock;
+
+/// MCP protocol versions this server can speak, oldest first.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct DescribeCollectionArguments {
+    collection_name: String,
+}
+
+#[derive(Deserialize)]
+struct SearchArguments {
+    collection_name: String,
+    query: String,
+    /// indexed column to search in. All indexed columns are searched if neither this nor `columns` is given
+    column_name: Option<String>,
+    /// indexed columns to search in with the weights of their scores
+    columns: Option<Vec<SearchColumn>>,
+    limit: Option<u32>,
+    min_score: Option<f32>,
+    #[serde(default)]
+    filters: BTreeMap<String, Value>,
+    /// additional columns to return with each result
+    #[serde(default)]
+    return_columns: Vec<String>,
+}
+
+/// Model Context Protocol server exposing the collections of a `CollectionManager` as tools.
+pub struct McpServer {
+    manager: web::Data<RwLock<CollectionManager>>,
+    /// maximum number of results the search tool returns
+    max_limit: u32,
+}
+
+impl McpServer {
+    pub fn new(manager: web::Data<RwLock<CollectionManager>>, max_limit: u32) -> Self {
+        McpServer { manager, max_limit }
+    }
+
+    /// Handles a JSON-RPC message or batch of messages and returns the response to send, if any.
+    /// With an `api_key`, tools only see the collections the key can access.
+    pub async fn handle_message(&self, message: &str, api_key: Option<&ApiKey>) -> Option<Value> {
+        let message: Value = match serde_json::from_str(message) {
+            Ok(message) => message,
+            Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+
+        match message {
+            Value::Array(messages) if messages.is_empty() => Some(error_response(
+                Value::Null,
+                INVALID_REQUEST,
+                String::from("Empty batch"),
+            )),
+            Value::Array(messages) => {
+                let mut responses = Vec::new();
+                for message in messages {
+                    if let Some(response) = self.handle_request(message, api_key).await {
+                        responses.push(response);
+                    }
+                }
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            }
+            message => self.handle_request(message, api_key).await,
+        }
+    }
+
+    async fn handle_request(&self, request: Value, api_key: Option<&ApiKey>) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = match request.get("method").and_then(|m| m.as_str()) {
+            Some(method) => method,
+            // responses to requests of the server, which never sends any
+            None if request.get("result").is_some() || request.get("error").is_some() => {
+                return None
+            }
+            None => {
+                return Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    INVALID_REQUEST,
+                    String::from("Missing method"),
+                ))
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(json!({}));
+
+        let result = match method {
+            "initialize" => Ok(initialize_result(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tool_definitions(self.max_limit) })),
+            "tools/call" => self.call_tool(&params, api_key).await,
+            _ if method.starts_with("notifications/") => return None,
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+
+        // notifications get no response
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        params: &Value,
+        api_key: Option<&ApiKey>,
+    ) -> Result<Value, (i64, String)> {
+        let name = params
+            .get("name")
+            .and_then(|name| name.as_str())
+            .ok_or((INVALID_PARAMS, String::from("Missing tool name")))?;
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+        let output = match name {
+            "list_collections" => self.list_collections(api_key).await,
+            "describe_collection" => self.describe_collection(arguments, api_key).await,
+            "search" => self.search(arguments, api_key).await,
+            _ => return Err((INVALID_PARAMS, format!("Unknown tool: {}", name))),
+        };
+
+        // errors of tools are reported to the model so that it can correct its call
+        Ok(match output {
+            Ok(output) => json!({
+                "content": [{
+                    "type": "text",
+                    "text": serde_json::to_string_pretty(&output).unwrap_or_default(),
+                }],
+                "structuredContent": output,
+                "isError": false,
+            }),
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": e.to_string() }],
+                "isError": true,
+            }),
+        })
+    }
+
+    async fn list_collections(&self, api_key: Option<&ApiKey>) -> anyhow::Result<Value> {
+        let configs = self.manager.read().await.get_collection_configs().await;
+        let collections: Vec<Value> = configs
+            .iter()
+            .filter(|config| api_key.is_none_or(|api_key| api_key.can_access(&config.name)))
+            .map(|config| {
+                json!({
+                    "name": config.name,
+                    "index_columns": config.index_columns,
+                    "model_name": config.model_name,
+                })
+            })
+            .collect();
+
+        Ok(json!({ "collections": collections }))
+    }
+
+    async fn describe_collection(
+        &self,
+        arguments: Value,
+        api_key: Option<&ApiKey>,
+    ) -> anyhow::Result<Value> {
+        let arguments: DescribeCollectionArguments = serde_json::from_value(arguments)?;
+        check_access(api_key, &arguments.collection_name)?;
+        let stats = self
+            .manager
+            .read()
+            .await
+            .get_collection_stats(arguments.collection_name)
+            .await?;
+
+        Ok(serde_json::to_value(stats)?)
+    }
+
+    async fn search(&self, arguments: Value, api_key: Option<&ApiKey>) -> anyhow::Result<Value> {
+        let arguments: SearchArguments = serde_json::from_value(arguments)?;
+        check_access(api_key, &arguments.collection_name)?;
+        let manager = self.manager.read().await;
+        let columns = match (arguments.column_name, arguments.columns) {
+            (Some(column_name), None) => vec![SearchColumn::new(&column_name)],
+            (None, Some(columns)) => columns,
+            (None, None) => manager
+                .get_collection_config(arguments.collection_name.clone())
+                .await?
+                .index_columns
+                .iter()
+                .map(|column| SearchColumn::new(column))
+                .collect(),
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "Provide either column_name or columns, not both"
+                ))
+            }
+        };
+        let options = SearchOptions {
+            columns,
+            limit: arguments.limit.unwrap_or(10),
+            min_score: arguments.min_score,
+            filters: arguments.filters,
+            ..SearchOptions::default()
+        };
+        options.validate()?;
+        options.check_limits(self.max_limit)?;
+
+        let results = manager
+            .search(
+                arguments.collection_name.clone(),
+                arguments.query.clone(),
+                options,
+            )
+            .await?;
+        let keys: Vec<u64> = results.iter().map(|result| result.key).collect();
+        let rows = manager
+            .get_rows(&arguments.collection_name, &keys, &arguments.return_columns)
+            .await?;
+
+        let hits: Vec<Value> = results
+            .iter()
+            .map(|result| {
+                let mut hit = json!({
+                    "key": result.key,
+                    "score": result.score,
+                    "content": result.content,
+                    "matched_columns": result.matched_columns,
+                });
+                if let Some(fields) = rows.get(&result.key) {
+                    hit.as_object_mut().unwrap().extend(fields.clone());
+                }
+                hit
+            })
+            .collect();
+
+        Ok(json!({ "results": hits }))
+    }
+}
+
+/// Errors if `api_key` is given and can't read `collection`.
+fn check_access(api_key: Option<&ApiKey>, collection: &str) -> anyhow::Result<()> {
+    match api_key {
+        Some(api_key) if !api_key.can_access(collection) => Err(anyhow::anyhow!(
+            "API key '{}' is not allowed to read collection '{}'",
+            api_key.name,
+            collection
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Result of `initialize`, agreeing on the version requested by the client if it's supported.
+fn initialize_result(params: &Value) -> Value {
+    let requested_version = params.get("protocolVersion").and_then(|v| v.as_str());
+    let protocol_version = match requested_version {
+        Some(version) if PROTOCOL_VERSIONS.contains(&version) => version,
+        _ => PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1],
+    };
+
+    json!({
+        "protocolVersion": protocol_version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "letsearch", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn tool_definitions(max_limit: u32) -> Value {
+    json!([
+        {
+            "name": "list_collections",
+            "description": "List the letsearch collections available for search with their indexed columns.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "describe_collection",
+            "description": "Describe a collection: its columns and their types, indexed columns, number of rows and embedding model.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "collection_name": { "type": "string" },
+                },
+                "required": ["collection_name"],
+            },
+        },
+        {
+            "name": "search",
+            "description": "Semantic search in a collection. Returns the most similar rows to the query with their scores.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "collection_name": { "type": "string" },
+                    "query": { "type": "string", "description": "text to search for" },
+                    "column_name": {
+                        "type": "string",
+                        "description": "indexed column to search in. All indexed columns are searched if omitted",
+                    },
+                    "columns": {
+                        "type": "array",
+                        "description": "indexed columns to search in with the weights of their scores, instead of column_name",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "column_name": { "type": "string" },
+                                "weight": { "type": "number" },
+                            },
+                            "required": ["column_name"],
+                        },
+                    },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": max_limit, "default": 10 },
+                    "min_score": { "type": "number", "description": "drop results scoring lower than this" },
+                    "filters": {
+                        "type": "object",
+                        "description": "keep only rows whose columns are equal to these values, or to one of them if a value is an array",
+                    },
+                    "return_columns": {
+                        "type": "array",
+                        "description": "other columns to return with each result",
+                        "items": { "type": "string" },
+                    },
+                },
+                "required": ["collection_name", "query"],
+            },
+        },
+    ])
+}
+
+/// Serves MCP over stdio: one JSON-RPC message per line on stdin and responses on stdout.
+/// Logs go to stderr so they don't interfere with the protocol.
+pub async fn run_mcp_stdio(manager: CollectionManager, max_limit: u32) -> anyhow::Result<()> {
+    let _server_locks = lock_collections(&manager).await?;
+    let server = McpServer::new(web::Data::new(RwLock::new(manager)), max_limit);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    info!("MCP server listening on stdio");
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle_message(&line, None).await {
+            stdout
+                .write_all(format!("{}\n", response).as_bytes())
+                .await?;
+            stdout.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a request comes from a page served from localhost, or not from a browser at all.
+/// Other origins are refused to prevent DNS rebinding attacks on local servers.
+fn is_allowed_origin(req: &HttpRequest) -> bool {
+    let origin = match req.headers().get("Origin") {
+        Some(origin) => origin.to_str().unwrap_or_default(),
+        None => return true,
+    };
+    let host = origin
+        .split("://")
+        .nth(1)
+        .unwrap_or_default()
+        .trim_start_matches('[');
+    ["localhost", "127.0.0.1", "::1]"]
+        .iter()
+        .any(|local| host == *local || host.starts_with(format!("{}:", local).as_str()))
+}
+
+async fn mcp_post(req: HttpRequest, body: String, server: web::Data<McpServer>) -> impl Responder {
+    if !is_allowed_origin(&req) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let api_key = match req.app_data::<web::Data<ApiKeys>>() {
+        Some(api_keys) => match api_keys.authenticate_headers(req.headers()) {
+            Some(api_key) => Some(api_key),
+            None => {
+                return HttpResponse::Unauthorized()
+                    .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                    .finish()
+            }
+        },
+        None => None,
+    };
+
+    match server.handle_message(&body, api_key).await {
+        Some(response) => HttpResponse::Ok().json(response),
+        None => HttpResponse::Accepted().finish(),
+    }
+}
+
+/// The server never sends messages on its own, so there is no stream to open.
+async fn mcp_get() -> impl Responder {
+    HttpResponse::MethodNotAllowed().finish()
+}
+
+/// Serves MCP over streamable HTTP at `/mcp`.
+/// With `api_keys`, requests must send one of them like requests to the REST API.
+pub async fn run_mcp_http(
+    host: String,
+    port: i32,
+    manager: CollectionManager,
+    max_limit: u32,
+    api_keys: Option<ApiKeys>,
+) -> std::io::Result<()> {
+    let _server_locks = lock_collections(&manager)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let server = web::Data::new(McpServer::new(
+        web::Data::new(RwLock::new(manager)),
+        max_limit,
+    ));
+    let api_keys = api_keys.map(web::Data::new);
+    info!("MCP server listening on http://{host}:{port}/mcp");
+
+    HttpServer::new(move || {
+        let mut app = App::new().app_data(server.clone());
+        if let Some(api_keys) = api_keys.as_ref() {
+            app = app.app_data(api_keys.clone());
+        }
+        app.wrap(Logger::new("from %a to %r with %s in %T secs"))
+            .route("/mcp", web::post().to(mcp_post))
+            .route("/mcp", web::get().to(mcp_get))
+    })
+    .bind(format!("{host}:{port}"))?
+    .run()
+    .await
+}
+
+/// Locks the loaded collections so that other processes don't modify them while they are served.
+async fn lock_collections(manager: &CollectionManager) -> anyhow::Result<Vec<ServerLock>> {
+    manager
+        .get_collections()
+        .await
+        .iter()
+        .map(|name| ServerLock::acquire(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mcp_post, McpServer};
+    use crate::auth::ApiKeys;
+    use crate::collection::collection_manager::CollectionManager;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{web, App};
+    use serde_json::json;
+    use std::fs;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_mcp_protocol() {
+        let server = McpServer::new(
+            web::Data::new(RwLock::new(CollectionManager::new(None))),
+            100,
+        );
+
+        let response = server
+            .handle_message(
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "test", "version": "1"}}}"#, None
+            )
+            .await
+            .unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(response["result"]["serverInfo"]["name"], "letsearch");
+
+        // notifications get no response
+        assert!(server
+            .handle_message(
+                r#"{"jsonrpc": "2.0", "method": "notifications/initialized"}"#,
+                None
+            )
+            .await
+            .is_none());
+
+        let response = server
+            .handle_message(
+                r#"{"jsonrpc": "2.0", "id": 2, "method": "tools/list"}"#,
+                None,
+            )
+            .await
+            .unwrap();
+        let tools: Vec<&str> = response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            tools,
+            vec!["list_collections", "describe_collection", "search"]
+        );
+
+        let response = server
+            .handle_message(
+                r#"{"jsonrpc": "2.0", "id": "3", "method": "tools/call", "params": {"name": "list_collections"}}"#, None
+            )
+            .await
+            .unwrap();
+        assert_eq!(response["id"], "3");
+        assert_eq!(response["result"]["isError"], false);
+        assert_eq!(
+            response["result"]["structuredContent"],
+            json!({ "collections": [] })
+        );
+
+        // tool errors are results for the model to see
+        let response = server
+            .handle_message(
+                r#"{"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "describe_collection", "arguments": {"collection_name": "missing"}}}"#, None
+            )
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+
+        let response = server
+            .handle_message(r#"[{"jsonrpc": "2.0", "id": 5, "method": "ping"}, {"jsonrpc": "2.0", "id": 6, "method": "unknown"}]"#, None)
+            .await
+            .unwrap();
+        assert_eq!(response[0]["result"], json!({}));
+        assert_eq!(response[1]["error"]["code"], -32601);
+
+        let response = server.handle_message("not json", None).await.unwrap();
+        assert_eq!(response["error"]["code"], -32700);
+    }
+
+    #[actix_web::test]
+    async fn test_mcp_api_keys() {
+        let path = std::env::temp_dir().join("test_mcp_api_keys.json");
+        fs::write(
+            &path,
+            r#"{"keys": [{"name": "docs", "key": "docs-key", "collections": ["docs"], "role": "read"}]}"#,
+        )
+        .unwrap();
+        let api_keys = ApiKeys::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let server = web::Data::new(McpServer::new(
+            web::Data::new(RwLock::new(CollectionManager::new(None))),
+            100,
+        ));
+        let app = init_service(
+            App::new()
+                .app_data(server)
+                .app_data(web::Data::new(api_keys))
+                .route("/mcp", web::post().to(mcp_post)),
+        )
+        .await;
+        let ping = r#"{"jsonrpc": "2.0", "id": 1, "method": "ping"}"#;
+
+        let req = TestRequest::post()
+            .uri("/mcp")
+            .set_payload(ping)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 401);
+        let req = TestRequest::post()
+            .uri("/mcp")
+            .insert_header(("Authorization", "Bearer wrong-key"))
+            .set_payload(ping)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 401);
+        let req = TestRequest::post()
+            .uri("/mcp")
+            .insert_header(("Authorization", "Bearer docs-key"))
+            .set_payload(ping)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+
+        // the key can't read other collections
+        let req = TestRequest::post()
+            .uri("/mcp")
+            .insert_header(("X-API-Key", "docs-key"))
+            .set_payload(
+                r#"{"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {"name": "describe_collection", "arguments": {"collection_name": "other"}}}"#,
+            )
+            .to_request();
+        let response: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(response["result"]["isError"], true);
+        assert!(response["result"]["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("not allowed"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::collection::collection_manager::CollectionManager;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter_vec,
+    register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounterVec, IntGaugeVec,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Instant;
+use tokio::sync::RwLock;
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "letsearch_http_requests_total",
+        "HTTP requests by route, method, collection and status",
+        &["route", "method", "collection", "status"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "letsearch_http_request_duration_seconds",
+        "Time to handle HTTP requests by route, method and collection",
+        &["route", "method", "collection"]
+    )
+    .unwrap()
+});
+
+pub static EMBEDDING_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "letsearch_embedding_duration_seconds",
+        "Time to embed a batch of texts",
+        exponential_buckets(0.001, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+pub static EMBEDDING_BATCH_SIZE: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "letsearch_embedding_batch_size",
+        "Number of texts in embedded batches",
+        exponential_buckets(1.0, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+pub static VECTOR_SEARCH_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "letsearch_vector_search_duration_seconds",
+        "Time of approximate nearest neighbor searches in vector indexes",
+        exponential_buckets(0.00005, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+pub static MODEL_LOAD_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "letsearch_model_load_duration_seconds",
+        "Time to download if needed and load models",
+        &["model"],
+        exponential_buckets(0.1, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+pub static INDEX_SIZE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "letsearch_index_vectors",
+        "Number of vectors in the index of each indexed column",
+        &["collection", "column"]
+    )
+    .unwrap()
+});
+
+pub static INDEX_MEMORY_USAGE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "letsearch_index_memory_bytes",
+        "Memory used by the index of each indexed column",
+        &["collection", "column"]
+    )
+    .unwrap()
+});
+
+/// Middleware that counts and times requests by route pattern, so that paths with
+/// different collection names or unknown paths don't create a series each.
+/// Only collections loaded in the `CollectionManager` of the app get their own label,
+/// the others are counted as `other`.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| String::from("unmatched"));
+    let method = req.method().to_string();
+    let manager = req
+        .app_data::<web::Data<RwLock<CollectionManager>>>()
+        .cloned();
+    let result = next.call(req).await;
+
+    let (collection, status) = match &result {
+        Ok(res) => (
+            res.request()
+                .match_info()
+                .get("collection_name")
+                .map(|name| name.to_string()),
+            res.status(),
+        ),
+        Err(e) => (None, e.as_response_error().status_code()),
+    };
+    let collection = match (collection, manager) {
+        (None, _) => String::new(),
+        (Some(name), Some(manager)) => {
+            if manager.read().await.get_collections().await.contains(&name) {
+                name
+            } else {
+                String::from("other")
+            }
+        }
+        (Some(_), None) => String::from("other"),
+    };
+    HTTP_REQUESTS
+        .with_label_values(&[&route, &method, &collection, status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&route, &method, &collection])
+        .observe(start.elapsed().as_secs_f64());
+
+    result
+}
+
+/// Updates the index gauges from the loaded collections and renders all metrics
+/// in the Prometheus text format.
+pub async fn render_metrics(manager: &CollectionManager) -> anyhow::Result<String> {
+    INDEX_SIZE.reset();
+    INDEX_MEMORY_USAGE.reset();
+    for name in manager.get_collections().await {
+        // a collection may be unloaded in the meantime
+        let Ok(stats) = manager.get_collection_stats(name.clone()).await else {
+            continue;
+        };
+        for index in stats.indexes {
+            INDEX_SIZE
+                .with_label_values(&[&name, &index.column])
+                .set(index.index.size as i64);
+            INDEX_MEMORY_USAGE
+                .with_label_values(&[&name, &index.column])
+                .set(index.index.memory_usage as i64);
+        }
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_metrics, track_requests, HTTP_REQUESTS, VECTOR_SEARCH_DURATION};
+    use crate::collection::collection_manager::CollectionManager;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+    use tokio::sync::RwLock;
+
+    #[actix_web::test]
+    async fn test_track_requests() {
+        let manager = web::Data::new(RwLock::new(CollectionManager::new(None)));
+        let app = test::init_service(
+            App::new()
+                .app_data(manager)
+                .wrap(from_fn(track_requests))
+                .route(
+                    "/collections/{collection_name}",
+                    web::get().to(HttpResponse::Ok),
+                ),
+        )
+        .await;
+        for uri in ["/collections/metrics_test", "/unknown/metrics_test"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let count = |labels: &[&str]| HTTP_REQUESTS.with_label_values(labels).get();
+        // the collection is not loaded, so arbitrary names don't create series
+        assert_eq!(
+            count(&["/collections/{collection_name}", "GET", "other", "200"]),
+            1
+        );
+        assert_eq!(count(&["unmatched", "GET", "", "404"]), 1);
+
+        VECTOR_SEARCH_DURATION.observe(0.001);
+        let text = render_metrics(&CollectionManager::new(None)).await.unwrap();
+        assert!(text.contains("letsearch_http_requests_total{"));
+        assert!(text.contains("letsearch_vector_search_duration_seconds_bucket"));
+    }
+}
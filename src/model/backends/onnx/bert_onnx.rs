@@ -253,4 +253,21 @@ impl ONNXModelTrait for BertONNX {
     async fn output_dim(&self) -> anyhow::Result<i64> {
         Ok(self.output_dim)
     }
+
+    async fn count_tokens(&self, texts: Vec<&str>) -> anyhow::Result<Vec<usize>> {
+        let inputs: Vec<String> = texts.iter().map(|s| s.to_string()).collect();
+        let tokenizer = self.tokenizer.clone();
+
+        task::spawn_blocking(move || {
+            let encodings = tokenizer
+                .encode_batch(inputs, true)
+                .map_err(|e| anyhow::anyhow!("Failed to tokenize inputs: {}", e))?;
+            // padding tokens are masked out
+            Ok(encodings
+                .iter()
+                .map(|e| e.get_attention_mask().iter().filter(|m| **m == 1).count())
+                .collect())
+        })
+        .await?
+    }
 }
@@ -1,3 +1,4 @@
 pub mod backends;
+pub mod model_cache;
 pub mod model_manager;
 pub mod model_utils;
@@ -0,0 +1,264 @@
+use super::model_utils::{ModelMetadata, MODEL_METADATA_FILE};
+use crate::collection::collection_utils::{dir_size, home_dir, list_collection_configs};
+use crate::hf_ops::{model_cache_dir, parse_model_path};
+use anyhow;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A model downloaded from HuggingFace Hub into `LETSEARCH_HOME/models`.
+pub struct CachedModel {
+    /// `hf://user/repo` or `hf://user/repo@revision` for pinned revisions
+    pub model_path: String,
+    pub dir: PathBuf,
+    pub metadata: ModelMetadata,
+    /// size on disk in bytes, excluding the pinned revisions cached under the same repo
+    pub size: u64,
+}
+
+impl CachedModel {
+    fn from_dir(model_path: String, dir: PathBuf) -> anyhow::Result<Self> {
+        let metadata = ModelMetadata::from_file(&dir.join(MODEL_METADATA_FILE))?;
+        let size = dir_size(&dir) - dir_size(&dir.join("revisions"));
+
+        Ok(CachedModel {
+            model_path,
+            dir,
+            metadata,
+            size,
+        })
+    }
+
+    /// Variants whose ONNX files are already downloaded
+    pub fn downloaded_variants(&self) -> Vec<&str> {
+        self.metadata
+            .variants
+            .iter()
+            .filter(|v| self.dir.join(v.path.as_str()).exists())
+            .map(|v| v.variant.as_str())
+            .collect()
+    }
+
+    /// Required files listed in `metadata.json` that are not downloaded
+    pub fn missing_files(&self) -> Vec<&str> {
+        self.metadata
+            .required_files
+            .iter()
+            .filter(|f| !self.dir.join(f.as_str()).exists())
+            .map(|f| f.as_str())
+            .collect()
+    }
+}
+
+fn subdirs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().to_string()
+}
+
+/// Lists the models in the local cache, including pinned revisions.
+pub fn list_cached_models() -> anyhow::Result<Vec<CachedModel>> {
+    let mut models = Vec::new();
+    for user_dir in subdirs(&home_dir().join("models"))? {
+        for repo_dir in subdirs(&user_dir)? {
+            let repo_path = format!("hf://{}/{}", dir_name(&user_dir), dir_name(&repo_dir));
+            let mut dirs = vec![(repo_path.clone(), repo_dir.clone())];
+            for revision_dir in subdirs(&repo_dir.join("revisions"))? {
+                let revision = dir_name(&revision_dir).replace("--", "/");
+                dirs.push((format!("{}@{}", repo_path, revision), revision_dir));
+            }
+
+            for (model_path, dir) in dirs {
+                if !dir.join(MODEL_METADATA_FILE).exists() {
+                    continue;
+                }
+                // a corrupt download shouldn't hide the other models
+                match CachedModel::from_dir(model_path.clone(), dir) {
+                    Ok(model) => models.push(model),
+                    Err(e) => warn!("Skipping cached model {}: {}", model_path, e),
+                }
+            }
+        }
+    }
+
+    Ok(models)
+}
+
+/// Returns the cached model at `model_path` (`hf://user/repo[@revision]`).
+pub fn get_cached_model(model_path: &str) -> anyhow::Result<CachedModel> {
+    let dir = model_cache_dir(model_path)?;
+    if !dir.join(MODEL_METADATA_FILE).exists() {
+        return Err(anyhow::anyhow!(
+            "Model {} is not in the local cache",
+            model_path
+        ));
+    }
+
+    let (repo_id, revision) = parse_model_path(model_path);
+    let model_path = match revision {
+        Some(revision) => format!("hf://{}@{}", repo_id, revision),
+        None => format!("hf://{}", repo_id),
+    };
+    CachedModel::from_dir(model_path, dir)
+}
+
+/// Names of the collections on disk that use the model at `model_path`.
+///
+/// Without a revision, any collection using the same repo is returned.
+/// With a revision, only the collections pinned to that revision are returned.
+pub fn collections_using_model(model_path: &str) -> anyhow::Result<Vec<String>> {
+    let (repo_id, revision) = parse_model_path(model_path);
+    let collections = list_collection_configs()?
+        .into_iter()
+        .filter(|config| {
+            let (config_repo_id, _) = parse_model_path(config.model_name.as_str());
+            let (_, config_revision) = parse_model_path(config.pinned_model_name().as_str());
+            config.model_name.starts_with("hf://")
+                && config_repo_id == repo_id
+                && (revision.is_none() || config_revision == revision)
+        })
+        .map(|config| config.name)
+        .collect();
+
+    Ok(collections)
+}
+
+/// Removes the model at `model_path` from the local cache.
+///
+/// Removing `hf://user/repo` also removes all the pinned revisions of that repo.
+/// It refuses to remove a model used by any collection unless `force` is set.
+pub fn remove_cached_model(model_path: &str, force: bool) -> anyhow::Result<()> {
+    let dir = model_cache_dir(model_path)?;
+    if !dir.exists() {
+        return Err(anyhow::anyhow!(
+            "Model {} is not in the local cache",
+            model_path
+        ));
+    }
+
+    let collections = collections_using_model(model_path)?;
+    if !collections.is_empty() && !force {
+        return Err(anyhow::anyhow!(
+            "Model {} is used by collection(s): {}. Use --force to remove it anyway",
+            model_path,
+            collections.join(", ")
+        ));
+    }
+
+    fs::remove_dir_all(&dir)?;
+    info!("Removed {} from {}", model_path, dir.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collections_using_model, get_cached_model, list_cached_models, remove_cached_model,
+    };
+    use crate::collection::collection_utils::{home_dir, CollectionConfig};
+    use crate::hf_ops::model_cache_dir;
+    use std::fs;
+
+    fn write_cached_model(model_path: &str) {
+        let dir = model_cache_dir(model_path).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        let metadata = r#"{
+            "letsearch_version": 1,
+            "variants": [
+                {"variant": "f32", "path": "model.onnx"},
+                {"variant": "i8", "path": "model-i8.onnx"}
+            ],
+            "required_files": ["tokenizer.json"]
+        }"#;
+        fs::write(dir.join("metadata.json"), metadata).unwrap();
+        fs::write(dir.join("model.onnx"), vec![0u8; 1000]).unwrap();
+    }
+
+    #[test]
+    fn test_model_cache() {
+        let repo_dir = model_cache_dir("hf://letsearch-test/cache").unwrap();
+        if repo_dir.exists() {
+            fs::remove_dir_all(&repo_dir).unwrap();
+        }
+        write_cached_model("hf://letsearch-test/cache");
+        write_cached_model("hf://letsearch-test/cache@abc123");
+
+        let models: Vec<String> = list_cached_models()
+            .unwrap()
+            .into_iter()
+            .map(|model| model.model_path)
+            .filter(|path| path.starts_with("hf://letsearch-test/cache"))
+            .collect();
+        assert_eq!(
+            models,
+            vec![
+                "hf://letsearch-test/cache",
+                "hf://letsearch-test/cache@abc123"
+            ]
+        );
+
+        // models with unreadable metadata are skipped
+        write_cached_model("hf://letsearch-test/cache@corrupt");
+        let corrupt_dir = model_cache_dir("hf://letsearch-test/cache@corrupt").unwrap();
+        fs::write(corrupt_dir.join("metadata.json"), "{not json").unwrap();
+        let models: Vec<String> = list_cached_models()
+            .unwrap()
+            .into_iter()
+            .map(|model| model.model_path)
+            .filter(|path| path.starts_with("hf://letsearch-test/cache"))
+            .collect();
+        assert_eq!(models.len(), 2);
+        fs::remove_dir_all(&corrupt_dir).unwrap();
+
+        let model = get_cached_model("hf://letsearch-test/cache").unwrap();
+        assert_eq!(model.downloaded_variants(), vec!["f32"]);
+        assert_eq!(model.missing_files(), vec!["tokenizer.json"]);
+        assert!(model.size >= 1000 && model.size < 2000);
+
+        // a collection pinned to the revision prevents removing it
+        let mut config = CollectionConfig::default();
+        config.name = String::from("test_model_cache_collection");
+        config.model_name = String::from("hf://letsearch-test/cache");
+        config.model_revision = Some(String::from("abc123"));
+        let collection_dir = home_dir().join("collections").join(config.name.as_str());
+        fs::create_dir_all(&collection_dir).unwrap();
+        fs::write(
+            collection_dir.join("config.json"),
+            serde_json::to_string(&config).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            collections_using_model("hf://letsearch-test/cache@abc123").unwrap(),
+            vec![config.name.clone()]
+        );
+        assert!(collections_using_model("hf://letsearch-test/cache@other")
+            .unwrap()
+            .is_empty());
+        assert!(remove_cached_model("hf://letsearch-test/cache", false).is_err());
+        remove_cached_model("hf://letsearch-test/cache@abc123", true).unwrap();
+        assert!(get_cached_model("hf://letsearch-test/cache@abc123").is_err());
+
+        assert!(remove_cached_model("hf://letsearch-test/..", true).is_err());
+        assert!(remove_cached_model("hf://letsearch-test/cache@..", true).is_err());
+        assert!(repo_dir.exists());
+
+        fs::remove_dir_all(&collection_dir).unwrap();
+        remove_cached_model("hf://letsearch-test/cache", false).unwrap();
+        assert!(!repo_dir.exists());
+    }
+}
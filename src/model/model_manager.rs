@@ -1,5 +1,6 @@
-use super::model_utils::{Backend, Embeddings, ModelOutputDType, ONNXModel};
+use super::model_utils::{resolve_local_model, Backend, Embeddings, ModelOutputDType, ONNXModel};
 use crate::hf_ops::download_model;
+use crate::metrics::{EMBEDDING_BATCH_SIZE, EMBEDDING_DURATION, MODEL_LOAD_DURATION};
 use crate::model::backends::onnx::bert_onnx::BertONNX;
 use crate::model::model_utils::ModelTrait;
 use anyhow::Error;
@@ -8,6 +9,7 @@ use log::info;
 use ndarray::Array2;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
 pub struct ModelManager {
@@ -30,17 +32,16 @@ impl ModelManager {
         model_type: Backend,
         token: Option<String>,
     ) -> anyhow::Result<u32> {
+        let start = Instant::now();
         let (model_dir, model_file) = if model_path.starts_with("hf://") {
             download_model(model_path.clone(), model_variant.clone(), token).await?
         } else {
-            (model_path.clone(), model_variant.clone())
+            resolve_local_model(model_path.as_str(), model_variant.as_str())?
         };
 
         let model: Arc<RwLock<dyn ONNXModel>> = match model_type {
             Backend::ONNX => Arc::new(RwLock::new(
-                BertONNX::new(model_dir.as_str(), model_file.as_str())
-                    .await
-                    .unwrap(),
+                BertONNX::new(model_dir.as_str(), model_file.as_str()).await?,
             )),
             // _ => unreachable!("not implemented"),
         };
@@ -51,11 +52,19 @@ impl ModelManager {
 
         let mut models = self.models.write().await;
         models.insert(model_id, model);
+        MODEL_LOAD_DURATION
+            .with_label_values(&[&model_path])
+            .observe(start.elapsed().as_secs_f64());
         info!("Model loaded from {}", model_path.as_str());
 
         Ok(model_id)
     }
 
+    /// Drops a loaded model. Requests still using its id get an error.
+    pub async fn unload_model(&self, model_id: u32) {
+        self.models.write().await.remove(&model_id);
+    }
+
     pub async fn predict_f16(
         &self,
         model_id: u32,
@@ -87,18 +96,24 @@ impl ModelManager {
     }
 
     pub async fn predict(&self, model_id: u32, texts: Vec<&str>) -> anyhow::Result<Embeddings> {
+        let start = Instant::now();
+        let batch_size = texts.len();
         let output_dtype = self.output_dtype(model_id).await?;
-        match output_dtype {
-            ModelOutputDType::F16 => Ok(Embeddings::F16(
-                self.predict_f16(model_id, texts).await.unwrap().to_owned(),
-            )),
-            ModelOutputDType::F32 => Ok(Embeddings::F32(
-                self.predict_f32(model_id, texts).await.unwrap().to_owned(),
-            )),
+        let embeddings = match output_dtype {
+            ModelOutputDType::F16 => {
+                Embeddings::F16(self.predict_f16(model_id, texts).await.unwrap().to_owned())
+            }
+            ModelOutputDType::F32 => {
+                Embeddings::F32(self.predict_f32(model_id, texts).await.unwrap().to_owned())
+            }
             ModelOutputDType::Int8 => {
                 unimplemented!("int8 dynamic quantization not yet implemented")
             }
-        }
+        };
+        EMBEDDING_BATCH_SIZE.observe(batch_size as f64);
+        EMBEDDING_DURATION.observe(start.elapsed().as_secs_f64());
+
+        Ok(embeddings)
     }
 
     pub async fn output_dtype(&self, model_id: u32) -> anyhow::Result<ModelOutputDType> {
@@ -112,6 +127,21 @@ impl ModelManager {
         }
     }
 
+    pub async fn count_tokens(
+        &self,
+        model_id: u32,
+        texts: Vec<&str>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let models = self.models.read().await;
+        match models.get(&model_id) {
+            Some(model) => {
+                let model_guard = model.read().await; // Lock the RwLock for reading
+                model_guard.count_tokens(texts).await
+            }
+            None => Err(Error::msg("Model not loaded")),
+        }
+    }
+
     pub async fn output_dim(&self, model_id: u32) -> anyhow::Result<i64> {
         let models = self.models.read().await;
         match models.get(&model_id) {
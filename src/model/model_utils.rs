@@ -2,8 +2,14 @@ use anyhow;
 use async_trait::async_trait;
 use half::f16;
 use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+pub const MODEL_METADATA_FILE: &str = "metadata.json";
+const SUPPORTED_METADATA_VERSION: i64 = 1;
+
 pub enum Backend {
     ONNX,
 }
@@ -21,6 +27,115 @@ pub enum Embeddings {
     F32(Arc<Array2<f32>>),
 }
 
+/// A single entry of the `variants` array in `metadata.json`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ModelVariant {
+    pub variant: String,
+    pub path: String,
+}
+
+/// Contents of the `metadata.json` file that every letsearch-compatible model ships with.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ModelMetadata {
+    pub letsearch_version: i64,
+    pub variants: Vec<ModelVariant>,
+    #[serde(default)]
+    pub required_files: Vec<String>,
+}
+
+impl ModelMetadata {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Could not read model metadata at {}: {}", path.display(), e)
+        })?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        if value.get("letsearch_version").is_none() {
+            return Err(anyhow::anyhow!(
+                "This is probably not a letsearch-compatible model: `letsearch_version` is missing in {}",
+                path.display()
+            ));
+        }
+
+        let metadata: ModelMetadata = serde_json::from_value(value)
+            .map_err(|e| anyhow::anyhow!("Invalid model metadata in {}: {}", path.display(), e))?;
+        if metadata.letsearch_version != SUPPORTED_METADATA_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported letsearch_version {} in {}. Only version {} is supported",
+                metadata.letsearch_version,
+                path.display(),
+                SUPPORTED_METADATA_VERSION
+            ));
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn variant(&self, variant: &str) -> anyhow::Result<&ModelVariant> {
+        self.variants
+            .iter()
+            .find(|v| v.variant == variant)
+            .ok_or_else(|| {
+                let available: Vec<&str> =
+                    self.variants.iter().map(|v| v.variant.as_str()).collect();
+                anyhow::anyhow!(
+                    "Variant '{}' not found in model metadata. Available variants: {}",
+                    variant,
+                    available.join(", ")
+                )
+            })
+    }
+}
+
+/// Resolves a model stored in a local directory into `(model_dir, model_file)`.
+///
+/// `model_path` can be a plain path or a `file://` URI pointing to a directory
+/// with the same layout as the models downloaded from HuggingFace Hub,
+/// i.e., a `metadata.json` file, the ONNX file for each variant and the required files.
+pub fn resolve_local_model(model_path: &str, variant: &str) -> anyhow::Result<(String, String)> {
+    let model_dir = PathBuf::from(model_path.strip_prefix("file://").unwrap_or(model_path));
+    if !model_dir.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Model directory {} does not exist",
+            model_dir.display()
+        ));
+    }
+
+    let metadata_path = model_dir.join(MODEL_METADATA_FILE);
+    if !metadata_path.exists() {
+        return Err(anyhow::anyhow!(
+            "{} not found in model directory {}",
+            MODEL_METADATA_FILE,
+            model_dir.display()
+        ));
+    }
+
+    let metadata = ModelMetadata::from_file(&metadata_path)?;
+    let model_file = metadata.variant(variant)?.path.clone();
+
+    let missing_files: Vec<&str> = std::iter::once(model_file.as_str())
+        .chain(metadata.required_files.iter().map(|f| f.as_str()))
+        .filter(|f| !model_dir.join(f).is_file())
+        .collect();
+    if !missing_files.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Missing file(s) in model directory {}: {}",
+            model_dir.display(),
+            missing_files.join(", ")
+        ));
+    }
+
+    Ok((model_dir.to_string_lossy().to_string(), model_file))
+}
+
+/// Embeddings of texts computed outside of a collection, e.g. for the `/v1/embeddings` endpoint.
+pub struct TextEmbeddings {
+    /// name of the model that computed the embeddings
+    pub model: String,
+    pub embeddings: Vec<Vec<f32>>,
+    /// total number of tokens in the texts
+    pub token_count: usize,
+}
+
 #[async_trait]
 pub trait ModelTrait {
     async fn new(model_dir: &str, model_file: &str) -> anyhow::Result<Self>
@@ -34,6 +149,8 @@ pub trait ONNXModelTrait: ModelTrait {
     async fn output_dim(&self) -> anyhow::Result<i64>;
     async fn predict_f16(&self, texts: Vec<&str>) -> anyhow::Result<Arc<Array2<f16>>>;
     async fn predict_f32(&self, texts: Vec<&str>) -> anyhow::Result<Arc<Array2<f32>>>;
+    /// Number of tokens of each text, including special tokens but not padding.
+    async fn count_tokens(&self, texts: Vec<&str>) -> anyhow::Result<Vec<usize>>;
 
     #[allow(dead_code)]
     fn backend(&self) -> Backend {
@@ -43,3 +160,67 @@ pub trait ONNXModelTrait: ModelTrait {
 
 pub trait ONNXModel: ModelTrait + ONNXModelTrait + Send + Sync {}
 impl<T> ONNXModel for T where T: ModelTrait + ONNXModelTrait + Send + Sync {}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_local_model, ModelMetadata};
+    use std::env::temp_dir;
+    use std::fs;
+
+    fn write_model_dir(name: &str, files: &[&str]) -> std::path::PathBuf {
+        let model_dir = temp_dir().join("letsearch_local_models").join(name);
+        if model_dir.exists() {
+            fs::remove_dir_all(&model_dir).unwrap();
+        }
+        fs::create_dir_all(&model_dir).unwrap();
+        let metadata = r#"{
+            "letsearch_version": 1,
+            "variants": [
+                {"variant": "f32", "path": "model.onnx"},
+                {"variant": "i8", "path": "model-i8.onnx"}
+            ],
+            "required_files": ["tokenizer.json"]
+        }"#;
+        fs::write(model_dir.join("metadata.json"), metadata).unwrap();
+        for file in files {
+            fs::write(model_dir.join(file), b"").unwrap();
+        }
+
+        model_dir
+    }
+
+    #[test]
+    fn test_resolve_local_model() {
+        let model_dir = write_model_dir("complete", &["model.onnx", "tokenizer.json"]);
+        let (dir, file) = resolve_local_model(model_dir.to_str().unwrap(), "f32").unwrap();
+        assert_eq!(dir, model_dir.to_string_lossy());
+        assert_eq!(file, "model.onnx");
+
+        let uri = format!("file://{}", model_dir.to_str().unwrap());
+        let (_, file) = resolve_local_model(uri.as_str(), "f32").unwrap();
+        assert_eq!(file, "model.onnx");
+    }
+
+    #[test]
+    fn test_resolve_local_model_errors() {
+        let model_dir = write_model_dir("incomplete", &["model.onnx"]);
+        let model_dir_str = model_dir.to_str().unwrap();
+
+        let err = resolve_local_model(model_dir_str, "f16").unwrap_err();
+        assert!(err.to_string().contains("Available variants: f32, i8"));
+
+        let err = resolve_local_model(model_dir_str, "i8").unwrap_err();
+        assert!(err.to_string().contains("model-i8.onnx, tokenizer.json"));
+
+        fs::remove_file(model_dir.join("metadata.json")).unwrap();
+        let err = resolve_local_model(model_dir_str, "f32").unwrap_err();
+        assert!(err.to_string().contains("metadata.json not found"));
+    }
+
+    #[test]
+    fn test_metadata_version() {
+        let path = temp_dir().join("letsearch_metadata_v2.json");
+        fs::write(&path, r#"{"letsearch_version": 2, "variants": []}"#).unwrap();
+        assert!(ModelMetadata::from_file(&path).is_err());
+    }
+}
@@ -1,20 +1,32 @@
-use crate::collection::collection_manager::CollectionManager;
-use crate::collection::collection_utils::SearchResult;
-use actix_web::middleware::Logger;
+use crate::auth::{require_api_key, ApiKey, ApiKeys};
+use crate::collection::collection_manager::{CollectionManager, SearchError};
+use crate::collection::collection_utils::{
+    collection_dir, collection_files_state, CollectionStats, FusionMethod, MmrOptions,
+    SearchColumn, SearchOptions, SearchResult, ServerLock,
+};
+use crate::grpc::run_grpc_server;
+use crate::metrics::{render_metrics, track_requests};
+use actix_web::middleware::{from_fn, Logger};
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use base64::Engine;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::time::Instant;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use utoipa::openapi::security::{self, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
-#[derive(Serialize)]
-struct ErrorResponse {
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorResponse {
     status: String,
     message: String,
     time: f64,
 }
 
 impl ErrorResponse {
-    fn new(message: String, start: Instant) -> Self {
+    pub(crate) fn new(message: String, start: Instant) -> Self {
         ErrorResponse {
             status: "error".to_string(),
             message: message,
@@ -23,7 +35,7 @@ impl ErrorResponse {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct SuccessResponse<T: Serialize> {
     data: T,
     status: String,
@@ -40,40 +52,217 @@ impl<T: Serialize> SuccessResponse<T> {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct QueryRequest {
-    column_name: String,
+    /// column to search in. Either this or `columns` is required
+    column_name: Option<String>,
+    /// columns to search in with the weights of their scores
+    columns: Option<Vec<SearchColumn>>,
     query: String,
     limit: Option<u32>,
+    /// how results from several columns are merged: max, sum or rrf
+    fusion: Option<FusionMethod>,
+    /// diversify results with maximal marginal relevance
+    mmr: Option<MmrOptions>,
+    /// keep only the best hit among those with the same value in this column
+    collapse_column: Option<String>,
+    /// drop hits scoring lower than this
+    min_score: Option<f32>,
+    /// number of hits to skip
+    offset: Option<u32>,
+    /// `next_cursor` of the previous page of the same search
+    cursor: Option<String>,
+    /// keep only hits whose columns are equal to these values, or to one of them if a value is an array
+    #[serde(default)]
+    filters: BTreeMap<String, serde_json::Value>,
 }
 
-#[derive(Serialize)]
+impl QueryRequest {
+    fn search_options(&self) -> anyhow::Result<SearchOptions> {
+        let columns = match (&self.column_name, &self.columns) {
+            (Some(column_name), None) => vec![SearchColumn::new(column_name)],
+            (None, Some(columns)) => columns.to_vec(),
+            (None, None) => return Err(anyhow::anyhow!("column_name or columns is required")),
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "Provide either column_name or columns, not both"
+                ))
+            }
+        };
+        let mut options = SearchOptions {
+            columns,
+            limit: self.limit.unwrap_or(10),
+            fusion: self.fusion.unwrap_or_default(),
+            mmr: self.mmr.clone(),
+            collapse_column: self.collapse_column.clone(),
+            min_score: self.min_score,
+            offset: self.offset.unwrap_or(0),
+            filters: self.filters.clone(),
+        };
+        if let Some(cursor) = self.cursor.as_ref() {
+            if self.offset.is_some() {
+                return Err(anyhow::anyhow!("Provide either offset or cursor, not both"));
+            }
+            options.apply_cursor(&self.query, cursor)?;
+        }
+        options.validate()?;
+
+        Ok(options)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 struct HelthcheckResponse {
     version: String,
     status: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct CollectionConfigPresentable {
     name: String,
     index_columns: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct CollectionsResponse {
     collections: Vec<CollectionConfigPresentable>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct SearchResultsResponse {
     results: Vec<SearchResult>,
+    /// cursor to send with the same search to get the next page, if there may be one
+    next_cursor: Option<String>,
+}
+
+/// Settings of the server shared by the handlers.
+struct ServerSettings {
+    /// maximum number of results per search request
+    max_limit: u32,
+}
+
+/// Maximum number of inputs in a request to `/v1/embeddings`, same as the OpenAI API.
+const MAX_EMBEDDING_INPUTS: usize = 2048;
+
+/// Checks the texts of an embedding request to the REST or gRPC API.
+pub(crate) fn check_embedding_inputs(texts: &[String]) -> anyhow::Result<()> {
+    if texts.is_empty() || texts.len() > MAX_EMBEDDING_INPUTS {
+        return Err(anyhow::anyhow!(
+            "input should have between 1 and {} texts",
+            MAX_EMBEDDING_INPUTS
+        ));
+    }
+    if texts.iter().any(|text| text.is_empty()) {
+        return Err(anyhow::anyhow!("input cannot contain empty strings"));
+    }
+
+    Ok(())
+}
+
+/// Input of `/v1/embeddings`: a single text or a list of texts.
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+/// Request of the OpenAI-compatible `/v1/embeddings` endpoint.
+#[derive(Deserialize, ToSchema)]
+struct EmbeddingRequest {
+    input: EmbeddingInput,
+    /// one of the models loaded for the served collections
+    model: String,
+    /// float (default) or base64 for little-endian f32 values encoded in base64
+    encoding_format: Option<String>,
+    /// only accepted if it's the output dimension of the model
+    dimensions: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum EmbeddingValue {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Serialize, ToSchema)]
+struct EmbeddingData {
+    object: String,
+    index: usize,
+    embedding: EmbeddingValue,
+}
+
+#[derive(Serialize, ToSchema)]
+struct EmbeddingUsage {
+    prompt_tokens: usize,
+    total_tokens: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct EmbeddingResponse {
+    object: String,
+    data: Vec<EmbeddingData>,
+    model: String,
+    usage: EmbeddingUsage,
+}
+
+/// Error in the shape of the OpenAI API, so that its clients can report it.
+#[derive(Serialize, ToSchema)]
+struct OpenAIErrorResponse {
+    error: OpenAIError,
+}
+
+#[derive(Serialize, ToSchema)]
+struct OpenAIError {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: String,
+    param: Option<String>,
+    code: Option<String>,
+}
+
+impl OpenAIErrorResponse {
+    fn new(message: String, param: Option<&str>) -> Self {
+        OpenAIErrorResponse {
+            error: OpenAIError {
+                message,
+                error_type: "invalid_request_error".to_string(),
+                param: param.map(|p| p.to_string()),
+                code: None,
+            },
+        }
+    }
+
+    fn server_error(message: String) -> Self {
+        OpenAIErrorResponse {
+            error: OpenAIError {
+                message,
+                error_type: "server_error".to_string(),
+                param: None,
+                code: None,
+            },
+        }
+    }
+}
+
+/// Encodes an embedding as base64 of its little-endian f32 values like the OpenAI API.
+fn encode_embedding_base64(embedding: &[f32]) -> String {
+    let bytes: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "server",
+    responses((status = 200, body = SuccessResponse<HelthcheckResponse>))
+)]
 async fn healthcheck() -> impl Responder {
     let start = Instant::now();
     let response = SuccessResponse::new(
         HelthcheckResponse {
-            version: "0.1.13".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
             status: "ok".to_string(),
         },
         start,
@@ -81,11 +270,51 @@ async fn healthcheck() -> impl Responder {
     HttpResponse::Ok().json(response)
 }
 
-async fn get_collections(manager: web::Data<RwLock<CollectionManager>>) -> impl Responder {
+/// Metrics in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "server",
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = String, content_type = "text/plain"),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse)
+    )
+)]
+async fn get_metrics(manager: web::Data<RwLock<CollectionManager>>) -> impl Responder {
+    let start = Instant::now();
+    match render_metrics(&*manager.read().await).await {
+        Ok(metrics) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(metrics),
+        Err(e) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(e.to_string(), start))
+        }
+    }
+}
+
+/// Lists the served collections, or those the API key can access.
+#[utoipa::path(
+    get,
+    path = "/collections",
+    tag = "collections",
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = SuccessResponse<CollectionsResponse>),
+        (status = 401, body = ErrorResponse)
+    )
+)]
+async fn get_collections(
+    manager: web::Data<RwLock<CollectionManager>>,
+    api_key: Option<web::ReqData<ApiKey>>,
+) -> impl Responder {
     let start = Instant::now();
     let configs = manager.read().await.get_collection_configs().await;
     let configs_presentable = configs
         .iter()
+        // only the collections the API key can access, if authentication is enabled
+        .filter(|c| api_key.as_ref().is_none_or(|key| key.can_access(&c.name)))
         .map(|c| CollectionConfigPresentable {
             name: c.name.to_string(),
             index_columns: c.index_columns.to_vec(),
@@ -100,6 +329,19 @@ async fn get_collections(manager: web::Data<RwLock<CollectionManager>>) -> impl
     HttpResponse::Ok().json(response)
 }
 
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_name}",
+    tag = "collections",
+    params(("collection_name" = String, Path)),
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = SuccessResponse<CollectionConfigPresentable>),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
 async fn get_collection(
     collection_name: web::Path<String>,
     manager: web::Data<RwLock<CollectionManager>>,
@@ -121,65 +363,522 @@ async fn get_collection(
     response
 }
 
-async fn search(
+/// Row count, schema, index and model details and disk usage of a collection.
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_name}/stats",
+    tag = "collections",
+    params(("collection_name" = String, Path)),
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = SuccessResponse<CollectionStats>),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn get_collection_stats(
+    collection_name: web::Path<String>,
+    manager: web::Data<RwLock<CollectionManager>>,
+) -> impl Responder {
+    let start = Instant::now();
+    let name = collection_name.into_inner();
+    let stats = manager.read().await.get_collection_stats(name).await;
+    let response = match stats {
+        Ok(stats) => HttpResponse::Ok().json(SuccessResponse::new(stats, start)),
+        Err(e) => HttpResponse::NotFound().json(ErrorResponse::new(e.to_string(), start)),
+    };
+
+    response
+}
+
+/// Loads the collection again from disk, e.g., after re-indexing it with the CLI.
+#[utoipa::path(
+    post,
+    path = "/collections/{collection_name}/reload",
+    tag = "collections",
+    params(("collection_name" = String, Path)),
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = SuccessResponse<CollectionConfigPresentable>),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+async fn reload_collection(
     collection_name: web::Path<String>,
-    req: web::Json<QueryRequest>,
     manager: web::Data<RwLock<CollectionManager>>,
+    server_lock: web::Data<ServerLock>,
 ) -> impl Responder {
     let start = Instant::now();
     let name = collection_name.into_inner();
-    let limit = req.limit.unwrap_or(10);
-    if limit < 1 || limit > 100 {
-        return HttpResponse::BadRequest().json(ErrorResponse::new(
-            String::from("Limit should be between 1 and 100"),
+    let manager = manager.read().await;
+    if !manager.get_collections().await.contains(&name) {
+        return HttpResponse::NotFound().json(ErrorResponse::new(
+            format!("Collection '{}' does not exist", name),
             start,
         ));
     }
 
+    let config = match manager.reload_collection(&name).await {
+        Ok(()) => {
+            refresh_server_lock(&server_lock);
+            manager.get_collection_config(name).await
+        }
+        Err(e) => Err(e),
+    };
+    match config {
+        Ok(config) => HttpResponse::Ok().json(SuccessResponse::new(
+            CollectionConfigPresentable {
+                name: config.name,
+                index_columns: config.index_columns,
+            },
+            start,
+        )),
+        Err(e) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(e.to_string(), start))
+        }
+    }
+}
+
+/// Searches indexed columns of a collection.
+#[utoipa::path(
+    post,
+    path = "/collections/{collection_name}/search",
+    tag = "collections",
+    params(("collection_name" = String, Path)),
+    request_body = QueryRequest,
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = SuccessResponse<SearchResultsResponse>),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+async fn search(
+    collection_name: web::Path<String>,
+    req: web::Json<QueryRequest>,
+    manager: web::Data<RwLock<CollectionManager>>,
+    settings: web::Data<ServerSettings>,
+) -> impl Responder {
+    let start = Instant::now();
+    let name = collection_name.into_inner();
+    let options = match req.search_options() {
+        Ok(options) => options,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new(e.to_string(), start)),
+    };
+    if let Err(e) = options.check_limits(settings.max_limit) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(e.to_string(), start));
+    }
+
     let results = manager
         .read()
         .await
-        .search(name, req.column_name.clone(), req.query.clone(), limit)
+        .search(name, req.query.clone(), options.clone())
         .await;
     let response = match results {
         Ok(results) => HttpResponse::Ok().json(SuccessResponse::new(
-            SearchResultsResponse { results: results },
+            SearchResultsResponse {
+                next_cursor: options.next_cursor(&req.query, results.len()),
+                results,
+            },
             start,
         )),
-        Err(e) => HttpResponse::NotFound().json(ErrorResponse::new(e.to_string(), start)),
+        Err(e @ SearchError::CollectionNotFound(_)) => {
+            HttpResponse::NotFound().json(ErrorResponse::new(e.to_string(), start))
+        }
+        Err(e @ SearchError::InvalidOptions(_)) => {
+            HttpResponse::BadRequest().json(ErrorResponse::new(e.to_string(), start))
+        }
+        Err(e @ SearchError::Failed(_)) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(e.to_string(), start))
+        }
     };
 
     response
 }
 
+/// Embeds texts with one of the models of the served collections, like the OpenAI API.
+#[utoipa::path(
+    post,
+    path = "/v1/embeddings",
+    tag = "embeddings",
+    request_body = EmbeddingRequest,
+    security(("bearer_auth" = []), ("api_key" = [])),
+    responses(
+        (status = 200, body = EmbeddingResponse),
+        (status = 400, body = OpenAIErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 404, body = OpenAIErrorResponse),
+        (status = 500, body = OpenAIErrorResponse)
+    )
+)]
+async fn create_embeddings(
+    req: web::Json<EmbeddingRequest>,
+    manager: web::Data<RwLock<CollectionManager>>,
+) -> impl Responder {
+    let req = req.into_inner();
+    let texts = match req.input {
+        EmbeddingInput::Single(text) => vec![text],
+        EmbeddingInput::Batch(texts) => texts,
+    };
+    if let Err(e) = check_embedding_inputs(&texts) {
+        return HttpResponse::BadRequest()
+            .json(OpenAIErrorResponse::new(e.to_string(), Some("input")));
+    }
+    let use_base64 = match req.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(format) => {
+            return HttpResponse::BadRequest().json(OpenAIErrorResponse::new(
+                format!(
+                    "Unsupported encoding_format: {}. Use float or base64",
+                    format
+                ),
+                Some("encoding_format"),
+            ))
+        }
+    };
+
+    let manager = manager.read().await;
+    let (model_name, _) = match manager.find_model(req.model.as_str()).await {
+        Ok(model) => model,
+        Err(e) => {
+            return HttpResponse::NotFound()
+                .json(OpenAIErrorResponse::new(e.to_string(), Some("model")))
+        }
+    };
+    if let Some(dimensions) = req.dimensions {
+        let output_dim = match manager.output_dim(req.model.as_str()).await {
+            Ok(output_dim) => output_dim,
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .json(OpenAIErrorResponse::server_error(e.to_string()))
+            }
+        };
+        if dimensions != output_dim {
+            return HttpResponse::BadRequest().json(OpenAIErrorResponse::new(
+                format!(
+                    "Model {} only supports {} dimensions",
+                    model_name, output_dim
+                ),
+                Some("dimensions"),
+            ));
+        }
+    }
+
+    let result = manager
+        .embed(
+            req.model.as_str(),
+            texts.iter().map(|text| text.as_str()).collect(),
+        )
+        .await;
+    let embeddings = match result {
+        Ok(embeddings) => embeddings,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(OpenAIErrorResponse::server_error(e.to_string()))
+        }
+    };
+
+    let data = embeddings
+        .embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| EmbeddingData {
+            object: "embedding".to_string(),
+            index,
+            embedding: if use_base64 {
+                EmbeddingValue::Base64(encode_embedding_base64(&embedding))
+            } else {
+                EmbeddingValue::Float(embedding)
+            },
+        })
+        .collect();
+
+    HttpResponse::Ok().json(EmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model: embeddings.model,
+        usage: EmbeddingUsage {
+            prompt_tokens: embeddings.token_count,
+            total_tokens: embeddings.token_count,
+        },
+    })
+}
+
+/// Documents the API keys that servers started with `--api-keys` require.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(security::ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "letsearch"),
+    modifiers(&SecuritySchemes),
+    paths(
+        healthcheck,
+        get_metrics,
+        get_collections,
+        get_collection,
+        get_collection_stats,
+        reload_collection,
+        search,
+        create_embeddings
+    )
+)]
+struct ApiDoc;
+
+/// Defines `configure_routes` to register the routes of the REST API and, for tests,
+/// `ROUTES` to check that the OpenAPI document has the same ones.
+macro_rules! api_routes {
+    ($($method:ident $path:literal => $handler:ident,)*) => {
+        fn configure_routes(cfg: &mut web::ServiceConfig) {
+            $(cfg.route($path, web::$method().to($handler));)*
+        }
+
+        #[cfg(test)]
+        const ROUTES: &[(&str, &str)] = &[$((stringify!($method), $path),)*];
+    };
+}
+
+api_routes! {
+    get "/" => healthcheck,
+    get "/metrics" => get_metrics,
+    get "/collections" => get_collections,
+    get "/collections/{collection_name}" => get_collection,
+    get "/collections/{collection_name}/stats" => get_collection_stats,
+    post "/collections/{collection_name}/reload" => reload_collection,
+    post "/collections/{collection_name}/search" => search,
+    post "/v1/embeddings" => create_embeddings,
+}
+
+/// Re-creates the lock file of the served collection if re-indexing it removed the file,
+/// so that other processes still see that the collection is served.
+fn refresh_server_lock(server_lock: &ServerLock) {
+    if let Err(e) = server_lock.refresh() {
+        warn!("Cannot refresh the server lock: {}", e);
+    }
+}
+
+/// Reloads the collection `name` when its files change on disk, checking them every `interval`.
+/// It waits until they stop changing for an `interval` so that a collection being re-indexed
+/// is not loaded halfway.
+async fn watch_collection(
+    manager: web::Data<RwLock<CollectionManager>>,
+    server_lock: web::Data<ServerLock>,
+    name: String,
+    interval: Duration,
+) {
+    let dir = collection_dir(&name);
+    let mut loaded_state = collection_files_state(&dir);
+    let mut last_state = loaded_state.clone();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let state = collection_files_state(&dir);
+        if state != loaded_state && state == last_state {
+            info!("Collection '{}' changed on disk, reloading it", name);
+            match manager.read().await.reload_collection(&name).await {
+                Ok(()) => refresh_server_lock(&server_lock),
+                Err(e) => warn!("Cannot reload collection '{}': {}", name, e),
+            }
+            // a failed reload is retried only when the files change again
+            loaded_state = state.clone();
+        }
+        last_state = state;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run_server(
     host: String,
     port: i32,
     collection_name: String,
     token: Option<String>,
+    max_limit: u32,
+    grpc_port: Option<i32>,
+    api_keys: Option<ApiKeys>,
+    watch_interval: Option<Duration>,
 ) -> std::io::Result<()> {
     let collection_manager = CollectionManager::new(token);
     let _ = collection_manager
-        .load_collection(collection_name)
+        .load_collection(collection_name.clone())
         .await
         .unwrap();
+    // held until the server stops so that other processes don't modify the collection
+    let server_lock = web::Data::new(
+        ServerLock::acquire(&collection_name).map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+    let watch_server_lock = server_lock.clone();
     let shared_manager = web::Data::new(RwLock::new(collection_manager));
-    HttpServer::new(move || {
-        App::new()
+    let settings = web::Data::new(ServerSettings { max_limit });
+    let api_keys = api_keys.map(web::Data::new);
+    let grpc_manager = shared_manager.clone();
+    let watch_manager = shared_manager.clone();
+    let grpc_api_keys = api_keys.clone();
+    let http_server = HttpServer::new(move || {
+        let mut app = App::new()
             .app_data(shared_manager.clone())
+            .app_data(server_lock.clone())
+            .app_data(settings.clone());
+        // require_api_key lets every request through without them
+        if let Some(api_keys) = api_keys.as_ref() {
+            app = app.app_data(api_keys.clone());
+        }
+        // requests rejected for their API key are not tracked
+        app.wrap(from_fn(track_requests))
+            .wrap(from_fn(require_api_key))
             .wrap(Logger::new("from %a to %r with %s in %T secs"))
-            .route("/", web::get().to(healthcheck))
-            .route("/collections", web::get().to(get_collections))
-            .route(
-                "/collections/{collection_name}",
-                web::get().to(get_collection),
-            )
-            .route(
-                "/collections/{collection_name}/search",
-                web::post().to(search),
-            )
+            .configure(configure_routes)
+            .service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", ApiDoc::openapi()))
     })
     .bind(format!("{host}:{port}"))?
-    .run()
-    .await
+    .run();
+
+    let grpc_server = async {
+        match grpc_port {
+            Some(grpc_port) => run_grpc_server(
+                host.clone(),
+                grpc_port,
+                grpc_manager,
+                max_limit,
+                grpc_api_keys,
+            )
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string())),
+            None => std::future::pending().await,
+        }
+    };
+    let watcher = async {
+        match watch_interval {
+            Some(interval) => {
+                watch_collection(
+                    watch_manager,
+                    watch_server_lock,
+                    collection_name.clone(),
+                    interval,
+                )
+                .await
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    // both servers stop when either of them does
+    tokio::select! {
+        result = http_server => result,
+        result = grpc_server => result,
+        _ = watcher => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_embedding_base64, ApiDoc, EmbeddingInput, EmbeddingRequest, QueryRequest, ROUTES,
+    };
+    use base64::Engine;
+    use std::collections::BTreeSet;
+    use utoipa::OpenApi;
+
+    #[test]
+    fn test_embedding_request() {
+        let req: EmbeddingRequest =
+            serde_json::from_str(r#"{"input": "hello", "model": "mys/minilm"}"#).unwrap();
+        assert!(matches!(req.input, EmbeddingInput::Single(text) if text == "hello"));
+        assert!(req.encoding_format.is_none());
+
+        let req: EmbeddingRequest = serde_json::from_str(
+            r#"{"input": ["a", "b"], "model": "mys/minilm", "encoding_format": "base64", "user": "me"}"#,
+        )
+        .unwrap();
+        assert!(matches!(req.input, EmbeddingInput::Batch(texts) if texts.len() == 2));
+
+        let encoded = encode_embedding_base64(&[1.0, -0.5]);
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        let decoded: Vec<f32> = bytes
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(decoded, vec![1.0, -0.5]);
+    }
+
+    #[test]
+    fn test_query_request_columns() {
+        let req: QueryRequest =
+            serde_json::from_str(r#"{"query": "hello", "column_name": "text"}"#).unwrap();
+        assert_eq!(req.search_options().unwrap().columns.len(), 1);
+
+        let req: QueryRequest = serde_json::from_str(r#"{"query": "hello"}"#).unwrap();
+        assert_eq!(
+            req.search_options().unwrap_err().to_string(),
+            "column_name or columns is required"
+        );
+
+        let req: QueryRequest = serde_json::from_str(
+            r#"{"query": "hello", "column_name": "text", "columns": [{"column_name": "title"}]}"#,
+        )
+        .unwrap();
+        assert!(req.search_options().is_err());
+    }
+
+    #[test]
+    fn test_openapi_routes() {
+        let openapi = ApiDoc::openapi();
+        let mut documented = BTreeSet::new();
+        for (path, item) in openapi.paths.paths.iter() {
+            let operations = [
+                ("get", &item.get),
+                ("post", &item.post),
+                ("put", &item.put),
+                ("patch", &item.patch),
+                ("delete", &item.delete),
+            ];
+            for (method, operation) in operations {
+                if operation.is_some() {
+                    documented.insert((method, path.as_str()));
+                }
+            }
+        }
+        let routes: BTreeSet<(&str, &str)> = ROUTES.iter().copied().collect();
+        assert_eq!(
+            documented, routes,
+            "routes and the OpenAPI document differ, update the #[utoipa::path] attributes and ApiDoc"
+        );
+
+        let spec: serde_json::Value = serde_json::from_str(&openapi.to_json().unwrap()).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(spec["components"]["schemas"].get("QueryRequest").is_some());
+        assert!(spec["components"]["securitySchemes"]
+            .get("bearer_auth")
+            .is_some());
+        assert!(spec["components"]["securitySchemes"]
+            .get("api_key")
+            .is_some());
+        // only the health check is public
+        assert!(spec["paths"]["/"]["get"].get("security").is_none());
+        let search = &spec["paths"]["/collections/{collection_name}/search"]["post"];
+        assert!(search.get("security").is_some());
+        assert!(search["responses"].get("401").is_some());
+    }
 }